@@ -0,0 +1,73 @@
+use std::fmt::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dtui::stateful_tree::{Nodes, StatefulTree, TreeOptions};
+use zbus_xml::Node;
+
+// About as many objects as systemd has units, each with a few interfaces full of members
+const OBJECTS: usize = 2000;
+
+fn interface_xml(name: &str) -> String {
+    let mut xml = format!("<interface name=\"{}\">", name);
+    for method in 0..10 {
+        let _ = write!(
+            xml,
+            "<method name=\"Method{}\"><arg name=\"input\" type=\"a{{sv}}\" direction=\"in\"/>\
+             <arg name=\"output\" type=\"(so)\" direction=\"out\"/></method>",
+            method
+        );
+    }
+    for property in 0..5 {
+        let _ = write!(
+            xml,
+            "<property name=\"Property{}\" type=\"as\" access=\"read\"/>",
+            property
+        );
+    }
+    for signal in 0..3 {
+        let _ = write!(
+            xml,
+            "<signal name=\"Signal{}\"><arg name=\"value\" type=\"u\"/></signal>",
+            signal
+        );
+    }
+    xml + "</interface>"
+}
+
+fn canned_nodes() -> Nodes {
+    let interfaces = [
+        "org.example.Unit",
+        "org.example.Service",
+        "org.example.Timer",
+    ]
+    .map(interface_xml)
+    .concat();
+    let xml = format!("<node>{}</node>", interfaces);
+    (0..OBJECTS)
+        .map(|object| {
+            let node = Node::from_reader(xml.as_bytes()).expect("canned xml is valid");
+            (format!("/org/example/unit/{}", object), Ok(node))
+        })
+        .collect()
+}
+
+fn rebuild(c: &mut Criterion) {
+    let nodes = canned_nodes();
+    let mut tree = StatefulTree::new();
+    c.bench_function("rebuild", |b| {
+        b.iter(|| tree.rebuild(&nodes, TreeOptions::default()))
+    });
+    let options = TreeOptions {
+        flatten_members: true,
+        sort_members: true,
+        explain_signatures: true,
+        label_width: Some(80),
+        ..TreeOptions::default()
+    };
+    c.bench_function("rebuild flattened and explained", |b| {
+        b.iter(|| tree.rebuild(&nodes, options))
+    });
+}
+
+criterion_group!(benches, rebuild);
+criterion_main!(benches);
@@ -1,242 +1,792 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{ErrorKind, Write},
+    time::{Duration, Instant},
+};
 
-use crossterm::event::{self, Event, KeyCode};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use dtui::stateful_tree::{args_with_direction, Nodes, StatefulTree, TreeOptions};
 use itertools::Itertools;
 use ratatui::{backend::Backend, Terminal};
 use tokio::sync::mpsc::Receiver;
-use tui_tree_widget::TreeItem;
-use zbus::names::OwnedBusName;
+use zbus::{
+    names::{OwnedBusName, OwnedInterfaceName, OwnedUniqueName},
+    zvariant::OwnedObjectPath,
+    DBusError,
+};
 use zbus_xml::ArgDirection;
 
 use crate::{
-    dbus_handler::DbusActorHandle, messages::AppMessage, stateful_list::StatefulList,
-    stateful_tree::StatefulTree, ui::ui,
+    clipboard,
+    dbus_handler::DbusActorHandle,
+    format::format_value,
+    keymap::{Action, KeyMap},
+    markdown::to_markdown,
+    messages::AppMessage,
+    stateful_list::StatefulList,
+    ui::{ui, Theme},
 };
 
+// Up or Down pressed again within this time counts as the key being held
+const REPEAT_WINDOW: Duration = Duration::from_millis(100);
+const ACCELERATE_AFTER: usize = 10;
+
 #[derive(PartialEq)]
 pub enum WorkingArea {
     Services,
     Objects,
 }
 
+/// Where to start, given on the command line. Each part is dropped once it has been found
+#[derive(Default)]
+pub struct Focus {
+    pub service: Option<OwnedBusName>,
+    /// Object to start walking the service at, instead of /
+    pub path: Option<OwnedObjectPath>,
+    /// Interface to open on the object at path, or on / when there is no path
+    pub interface: Option<String>,
+}
+
+/// Order of the services list
+#[derive(Clone, Copy, PartialEq)]
+pub enum ServiceSort {
+    /// The order the bus lists them in
+    Bus,
+    /// By the process id of their owner, names without one last
+    Pid,
+    /// Grouped by the unique name of their owner, each unique name followed by the names it owns
+    Owner,
+}
+
+/// Result of GetAll for an interface, shown in a popup over the panes
+pub struct PropertiesView {
+    pub title: String,
+    pub properties: Result<Vec<(String, String)>, CallError>,
+    pub scroll: u16,
+    // Show the values in full over the whole terminal, instead of cut to max_value_length
+    pub full: bool,
+}
+
+/// A failed call, with the D-Bus error name kept apart from the message when the peer sent one
+pub struct CallError {
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl From<zbus::Error> for CallError {
+    fn from(err: zbus::Error) -> Self {
+        match err {
+            zbus::Error::MethodError(name, message, _) => CallError {
+                name: Some(name.to_string()),
+                message: message.unwrap_or_default(),
+            },
+            zbus::Error::FDO(err) => CallError {
+                name: Some(err.name().to_string()),
+                message: err.description().unwrap_or_default().to_string(),
+            },
+            err => CallError {
+                name: None,
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl CallError {
+    /// Short explanation of the well-known error names
+    pub fn explanation(&self) -> Option<&'static str> {
+        let explanation = match self.name.as_deref()? {
+            "org.freedesktop.DBus.Error.UnknownMethod" => "The object does not have this method",
+            "org.freedesktop.DBus.Error.UnknownObject" => "There is no object at this path",
+            "org.freedesktop.DBus.Error.UnknownInterface" => {
+                "The object does not implement this interface"
+            }
+            "org.freedesktop.DBus.Error.UnknownProperty" => "The interface has no such property",
+            "org.freedesktop.DBus.Error.ServiceUnknown" => "The service is not on the bus",
+            "org.freedesktop.DBus.Error.NameHasNoOwner" => "Nothing owns the name anymore",
+            "org.freedesktop.DBus.Error.AccessDenied" => "The bus policy does not allow this call",
+            "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired" => {
+                "The call needs authorization, e.g. through polkit"
+            }
+            "org.freedesktop.DBus.Error.NoReply" => "The service did not answer in time",
+            "org.freedesktop.DBus.Error.InvalidArgs" => "The arguments were not what was expected",
+            "org.freedesktop.DBus.Error.NotSupported" => "The service does not support this",
+            _ => return None,
+        };
+        Some(explanation)
+    }
+}
+
 pub struct App<'a> {
     dbus_rx: Receiver<AppMessage>,
     dbus_handle: DbusActorHandle,
+    // The services matching the filter, out of all_services
     pub services: StatefulList<OwnedBusName>,
+    all_services: Vec<OwnedBusName>,
+    pub service_filter: String,
+    // Whether the services have arrived since they were last requested
+    pub services_listed: bool,
+    // Whether keys go to the filter input
+    pub filtering: bool,
+    pub service_sort: ServiceSort,
+    // Services pinned to the top of the list, kept between runs
+    pub favorites: BTreeSet<String>,
+    focus: Focus,
+    // Owner process ids of the services, only fetched when sorting on them
+    pids: HashMap<OwnedBusName, u32>,
+    // Unique names owning the well-known names, only fetched when grouping on them
+    owners: HashMap<OwnedBusName, OwnedUniqueName>,
     pub objects: StatefulTree<'a>,
+    // The service the objects belong to, None when connected to a peer
+    objects_service: Option<OwnedBusName>,
+    nodes: Nodes,
+    pub tree_options: TreeOptions,
+    // Whether more objects of the service are still coming
+    pub walking: bool,
+    // Why walking the service stopped early, if it did
+    pub objects_error: Option<String>,
+    // Interface given with --implements, the filter can be toggled off in the UI
+    pub implements: Option<String>,
+    pub filter_implements: bool,
+    // Address of the peer when connected directly to one instead of a bus
+    pub peer: Option<String>,
+    // Address of the bus or peer that is connected to
+    address: String,
+    pub properties_view: Option<PropertiesView>,
+    // The popup listing every key, the footer only has room for the essential ones
+    pub show_help: bool,
+    // Width and height of popups, in percent of the terminal
+    pub popup_size: (u16, u16),
+    // Longer values are cut short in popups, until they are shown in full
+    pub max_value_length: usize,
+    // Move further while Up or Down is held, given with --accelerate
+    pub accelerate: bool,
+    // Number of items that fit in a pane, updated when drawing
+    pub page_size: usize,
+    // The last Up or Down key, when it was pressed and how many times it was repeated before that
+    last_navigation: Option<(KeyCode, Instant, usize)>,
+    // Leave out borders and spacing, for small terminals
+    pub dense: bool,
+    pub theme: Theme,
+    // Keys of the actions that can be bound to other keys
+    pub keymap: KeyMap,
+    // Shown in the footer until the next key press
+    pub status: Option<String>,
 
     pub working_area: WorkingArea,
 }
 
 impl<'a> App<'a> {
-    pub fn new(dbus_rx: Receiver<AppMessage>, dbus_handle: DbusActorHandle) -> App<'a> {
+    pub fn new(
+        dbus_rx: Receiver<AppMessage>,
+        dbus_handle: DbusActorHandle,
+        implements: Option<String>,
+        peer: Option<String>,
+        dense: bool,
+        focus: Focus,
+        address: String,
+    ) -> App<'a> {
         App {
             dbus_rx,
             dbus_handle,
             services: StatefulList::with_items(vec![]),
-            objects: StatefulTree::new(),
-            working_area: WorkingArea::Services,
+            all_services: vec![],
+            service_filter: String::new(),
+            services_listed: false,
+            filtering: false,
+            service_sort: ServiceSort::Bus,
+            favorites: BTreeSet::new(),
+            focus,
+            pids: HashMap::new(),
+            owners: HashMap::new(),
+            objects: match &peer {
+                Some(address) => StatefulTree::with_root_label(address.clone()),
+                None => StatefulTree::new(),
+            },
+            objects_service: None,
+            nodes: Nodes::new(),
+            tree_options: TreeOptions::default(),
+            // A peer is walked as soon as the app starts
+            walking: peer.is_some(),
+            objects_error: None,
+            filter_implements: implements.is_some(),
+            implements,
+            working_area: if peer.is_some() {
+                WorkingArea::Objects
+            } else {
+                WorkingArea::Services
+            },
+            peer,
+            address,
+            properties_view: None,
+            show_help: false,
+            popup_size: (80, 50),
+            max_value_length: 200,
+            accelerate: false,
+            last_navigation: None,
+            page_size: 1,
+            dense,
+            theme: Theme::default(),
+            keymap: KeyMap::default(),
+            status: None,
         }
     }
 
     pub fn on_tick(&self) {}
+
+    /// The service whose objects are shown, if any
+    pub fn objects_service(&self) -> Option<&OwnedBusName> {
+        self.objects_service.as_ref()
+    }
+
+    /// Grow or shrink popups by step percent, keeping them between 20% and 100% of the terminal
+    pub fn resize_popup(&mut self, step: i16) {
+        let resize = |size: u16| (size as i16 + step).clamp(20, 100) as u16;
+        self.popup_size = (resize(self.popup_size.0), resize(self.popup_size.1));
+    }
+
+    // How many items Up or Down moves. With acceleration holding the key moves a page at a time
+    // after a while, as the terminal repeats the key faster than a person can press it
+    fn navigation_step(&mut self, code: KeyCode) -> usize {
+        if !self.accelerate {
+            return 1;
+        }
+        let now = Instant::now();
+        let repeats = match self.last_navigation {
+            Some((last, at, repeats)) if last == code && now - at < REPEAT_WINDOW => repeats + 1,
+            _ => 0,
+        };
+        self.last_navigation = Some((code, now, repeats));
+        if repeats >= ACCELERATE_AFTER {
+            self.page_size
+        } else {
+            1
+        }
+    }
+
+    fn page_down(&mut self) {
+        match self.working_area {
+            WorkingArea::Services => self.services.down_by(self.page_size),
+            WorkingArea::Objects => self.objects.down_by(self.page_size),
+        }
+    }
+
+    fn page_up(&mut self) {
+        match self.working_area {
+            WorkingArea::Services => self.services.up_by(self.page_size),
+            WorkingArea::Objects => self.objects.up_by(self.page_size),
+        }
+    }
+
+    pub fn toggle_flatten_members(&mut self) {
+        self.tree_options.flatten_members = !self.tree_options.flatten_members;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    pub fn toggle_sort_members(&mut self) {
+        self.tree_options.sort_members = !self.tree_options.sort_members;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    /// Cut tree labels to the width of the objects pane, rebuilding the tree when it changed
+    pub fn fit_labels(&mut self, width: usize) {
+        if self.tree_options.label_width != Some(width) {
+            self.tree_options.label_width = Some(width);
+            self.objects.rebuild(&self.nodes, self.tree_options);
+        }
+    }
+
+    pub fn toggle_indent_paths(&mut self) {
+        self.tree_options.indent_paths = !self.tree_options.indent_paths;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    pub fn toggle_standard_interfaces(&mut self) {
+        self.tree_options.standard_interfaces = !self.tree_options.standard_interfaces;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    pub fn toggle_explain_signatures(&mut self) {
+        self.tree_options.explain_signatures = !self.tree_options.explain_signatures;
+        // Labels are made again from the cached introspection data, the bus is not asked again
+        self.objects.rebuild(&self.nodes, self.tree_options);
+        self.status = Some(
+            if self.tree_options.explain_signatures {
+                "Showing types in words, press t for signatures"
+            } else {
+                "Showing signatures, press t for types in words"
+            }
+            .to_string(),
+        );
+    }
+
+    pub fn toggle_explicit_directions(&mut self) {
+        self.tree_options.explicit_directions = !self.tree_options.explicit_directions;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    pub fn toggle_hide_standard_interfaces(&mut self) {
+        self.tree_options.hide_standard_interfaces = !self.tree_options.hide_standard_interfaces;
+        self.objects.rebuild(&self.nodes, self.tree_options);
+    }
+
+    fn apply_service_filter(&mut self) {
+        let filter = self.service_filter.to_lowercase();
+        let mut filtered: Vec<OwnedBusName> = self
+            .all_services
+            .iter()
+            .filter(|name| name.to_lowercase().contains(&filter))
+            .cloned()
+            .collect();
+        match self.service_sort {
+            ServiceSort::Bus => {}
+            ServiceSort::Pid => {
+                filtered.sort_by_key(|name| self.pids.get(name).map_or((1, 0), |pid| (0, *pid)));
+            }
+            // Names without a known owner last, the owner itself before the names it owns
+            ServiceSort::Owner => filtered.sort_by_cached_key(|name| {
+                let owner = self.owner(name).map(str::to_string);
+                let owns_itself = owner.as_deref() == Some(name.as_str());
+                (owner.is_none(), owner, !owns_itself, name.clone())
+            }),
+        }
+        // Stable, so favorites keep the order they had among themselves
+        filtered.sort_by_key(|name| !self.favorites.contains(name.as_str()));
+        self.services.replace_items(filtered);
+    }
+
+    /// The unique name owning a service, a unique name owns itself
+    pub fn owner<'n>(&'n self, name: &'n OwnedBusName) -> Option<&'n str> {
+        if name.starts_with(':') {
+            Some(name.as_str())
+        } else {
+            self.owners.get(name).map(|owner| owner.as_str())
+        }
+    }
+
+    /// Sort the services the given way, or go back to the order of the bus if they already are
+    async fn toggle_service_sort(&mut self, sort: ServiceSort) {
+        self.service_sort = if self.service_sort == sort {
+            ServiceSort::Bus
+        } else {
+            sort
+        };
+        self.request_sort_keys(self.all_services.clone()).await;
+        self.apply_service_filter();
+    }
+
+    // Process ids and owners are only fetched when sorting on them
+    async fn request_sort_keys(&self, names: Vec<OwnedBusName>) {
+        match self.service_sort {
+            ServiceSort::Bus => {}
+            ServiceSort::Pid => self.dbus_handle.request_pids(names).await,
+            ServiceSort::Owner => {
+                let well_known = names.into_iter().filter(|name| !name.starts_with(':'));
+                self.dbus_handle.request_owners(well_known.collect()).await;
+            }
+        }
+    }
+
+    /// Pin the selected service to the top of the list, or unpin it
+    fn toggle_favorite(&mut self) {
+        let Some(selected) = self.services.state.selected() else {
+            return;
+        };
+        let service = self.services.items[selected].to_string();
+        if !self.favorites.remove(&service) {
+            self.favorites.insert(service);
+        }
+        self.apply_service_filter();
+    }
+
+    /// Edit the filter, Enter keeps it and Esc cancels it
+    fn filter_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char(c) => self.service_filter.push(c),
+            KeyCode::Backspace => {
+                self.service_filter.pop();
+            }
+            KeyCode::Enter => self.filtering = false,
+            KeyCode::Esc => {
+                self.service_filter.clear();
+                self.filtering = false;
+            }
+            _ => return,
+        }
+        self.apply_service_filter();
+    }
+
+    /// The interface services are currently filtered on, if any
+    pub fn implements_filter(&self) -> Option<&str> {
+        self.implements
+            .as_deref()
+            .filter(|_| self.filter_implements)
+    }
+
+    async fn request_all_properties(&self) {
+        if let Some((path, interface)) = self.objects.selected_interface() {
+            let (Ok(path), Ok(interface)) = (
+                OwnedObjectPath::try_from(path),
+                OwnedInterfaceName::try_from(interface),
+            ) else {
+                return;
+            };
+            self.dbus_handle
+                .request_all_properties(self.objects_service.clone(), path, interface)
+                .await;
+        }
+    }
+
+    // GetAll together with the methods of the interface that look like getters: named Get..., taking no
+    // arguments and returning something. Other methods could have side effects, so they are left out
+    async fn request_snapshot(&self) {
+        let Some((path, interface)) = self.objects.selected_interface() else {
+            return;
+        };
+        let getters = match self.nodes.get(path) {
+            Some(Ok(node)) => node
+                .interfaces()
+                .iter()
+                .filter(|candidate| candidate.name() == interface)
+                .flat_map(|interface| interface.methods())
+                .filter(|method| {
+                    method.name().starts_with("Get")
+                        && args_with_direction(method.args(), ArgDirection::In, false).is_empty()
+                        && !args_with_direction(method.args(), ArgDirection::Out, false).is_empty()
+                })
+                .map(|method| method.name().to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let (Ok(path), Ok(interface)) = (
+            OwnedObjectPath::try_from(path),
+            OwnedInterfaceName::try_from(interface),
+        ) else {
+            return;
+        };
+        self.dbus_handle
+            .request_snapshot(self.objects_service.clone(), path, interface, getters)
+            .await;
+    }
+
+    // Writes the objects as Markdown to a file named after the service, in the current directory
+    fn export_markdown(&mut self) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let service = self
+            .objects_service
+            .as_ref()
+            .map_or("peer", |service| service.as_str());
+        let file_name = format!("{}.md", service);
+        // An earlier export, or any other file of that name, is left alone
+        let written = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file_name)
+            .and_then(|mut file| file.write_all(to_markdown(service, &self.nodes).as_bytes()));
+        self.status = Some(match written {
+            Ok(()) => format!("Exported to {}", file_name),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                format!("{} already exists, move it away to export again", file_name)
+            }
+            Err(err) => format!("Could not export to {}: {}", file_name, err),
+        });
+    }
+
+    async fn request_objects(&mut self, service: OwnedBusName, path: OwnedObjectPath) {
+        self.objects_service = Some(service.clone());
+        self.objects = StatefulTree::with_root_label(service.to_string());
+        self.nodes.clear();
+        self.walking = true;
+        self.objects_error = None;
+        self.dbus_handle
+            .request_objects_from(Some(service), path)
+            .await;
+    }
+
+    // Opens the interface given with --interface once its object has been found
+    fn open_focus_interface(&mut self, complete: bool) {
+        let Some(interface) = &self.focus.interface else {
+            return;
+        };
+        let path = self.focus.path.as_deref().map_or("/", |path| path.as_str());
+        if self.objects.open_interface(path, interface) {
+            self.focus.interface = None;
+        } else if complete {
+            self.status = Some(format!("{} has no interface {}", path, interface));
+            self.focus.interface = None;
+        }
+    }
+
+    fn copy_address(&mut self) {
+        self.status = Some(match clipboard::copy(&self.address) {
+            Ok(()) => format!("Copied {}", self.address),
+            Err(err) => format!("Could not copy the address: {}", err),
+        });
+    }
+
+    async fn request_services(&mut self) {
+        self.services_listed = false;
+        self.dbus_handle
+            .request_services(self.implements_filter().map(str::to_string))
+            .await;
+    }
+}
+
+fn root_path() -> OwnedObjectPath {
+    OwnedObjectPath::try_from("/").expect("/ is always a valid path")
 }
 
 pub async fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
-    mut app: App<'static>,
+    app: &mut App<'static>,
     tick_rate: Duration,
 ) -> Result<(), zbus::Error> {
     let mut last_tick = Instant::now();
-    app.dbus_handle.request_services().await;
+    app.request_services().await;
+    // Introspect the service given with --service right away, it is selected when the services arrive
+    if let Some(service) = app.focus.service.clone().filter(|_| app.peer.is_none()) {
+        let path = app.focus.path.clone().unwrap_or_else(root_path);
+        app.working_area = WorkingArea::Objects;
+        app.request_objects(service, path).await;
+    }
 
     loop {
-        terminal.draw(|frame| ui::<B>(frame, &mut app))?;
-
-        match app.dbus_rx.try_recv() {
-            Ok(message) => match message {
-                AppMessage::Objects(nodes) => {
-                    app.objects = StatefulTree::with_items(
-                        nodes
-                            .iter()
-                            .sorted_by(|a, b| a.0.cmp(b.0))
-                            .enumerate()
-                            .map(|(id, (object_name, node))| {
-                                let children: Vec<TreeItem<usize>> = node
-                                    .interfaces()
-                                    .iter()
-                                    .enumerate()
-                                    .map(|(id, interface)| {
-                                        let methods: Vec<TreeItem<usize>> = interface
-                                            .methods()
-                                            .iter()
-                                            .enumerate()
-                                            .map(|(id, method)| {
-                                                let inputs: Vec<String> = method
-                                                    .args()
-                                                    .iter()
-                                                    .filter(|arg| {
-                                                        arg.direction()
-                                                            .is_some_and(|s| s == ArgDirection::In)
-                                                    })
-                                                    .map(|arg| {
-                                                        format!(
-                                                            "{}: {}",
-                                                            arg.name().unwrap_or_default(),
-                                                            arg.ty()
-                                                        )
-                                                    })
-                                                    .collect();
-                                                let outputs: Vec<String> = method
-                                                    .args()
-                                                    .iter()
-                                                    .filter(|arg| {
-                                                        arg.direction()
-                                                            .is_some_and(|s| s == ArgDirection::Out)
-                                                    })
-                                                    .map(|arg| {
-                                                        format!(
-                                                            "{}: {}",
-                                                            arg.name().unwrap_or_default(),
-                                                            arg.ty()
-                                                        )
-                                                    })
-                                                    .collect();
-                                                let return_arrow =
-                                                    if outputs.is_empty() { "" } else { "=>" }; // If we dont return anything, the arrow shouldnt be there
-                                                let leaf_string: String = format!(
-                                                    "{}({}) {} {}",
-                                                    method.name(),
-                                                    inputs.join(", "),
-                                                    return_arrow,
-                                                    outputs.join(", ")
-                                                );
-                                                TreeItem::new_leaf(id, leaf_string)
-                                            })
-                                            .collect();
-                                        let properties: Vec<TreeItem<usize>> = interface
-                                            .properties()
-                                            .iter()
-                                            .enumerate()
-                                            .map(|(id, property)| {
-                                                TreeItem::new_leaf(
-                                                    id,
-                                                    format!(
-                                                        "{}: {}",
-                                                        property.name(),
-                                                        property.ty()
-                                                    ),
-                                                )
-                                            })
-                                            .collect();
-                                        let signals: Vec<TreeItem<usize>> = interface
-                                            .signals()
-                                            .iter()
-                                            .enumerate()
-                                            .map(|(id, signal)| {
-                                                // Signals can only have input parameters
-                                                let inputs: Vec<String> = signal
-                                                    .args()
-                                                    .iter()
-                                                    .filter(|arg| {
-                                                        arg.direction()
-                                                            .is_some_and(|s| s == ArgDirection::In)
-                                                    })
-                                                    .map(|arg| {
-                                                        format!(
-                                                            "{}: {}",
-                                                            arg.name().unwrap_or_default(),
-                                                            arg.ty()
-                                                        )
-                                                    })
-                                                    .collect();
-                                                let leaf_string: String = format!(
-                                                    "{}({})",
-                                                    signal.name(),
-                                                    inputs.join(", ")
-                                                );
-                                                TreeItem::new_leaf(id, leaf_string)
-                                            })
-                                            .collect();
-                                        // let annotations: Vec<TreeItem> = interface
-                                        //     .annotations()
-                                        //     .iter()
-                                        //     .map(|annotation| {
-                                        //         TreeItem::new_leaf(annotation.name().to_string())
-                                        //     })
-                                        //     .collect();
-                                        let methods_tree = TreeItem::new(0, "Methods", methods)
-                                            .expect("Methods should have different ids");
-                                        let properties_tree =
-                                            TreeItem::new(1, "Properties", properties)
-                                                .expect("Properties should have different ids");
-                                        let signals_tree = TreeItem::new(2, "Signals", signals)
-                                            .expect("Signals should have different ids");
-                                        // let annotations_tree =
-                                        //     TreeItem::new("Annotations", annotations);
-                                        // TODO: Annotations are used differently, so i dont want to waste space with it
-                                        TreeItem::new(
-                                            id,
-                                            interface.name().to_string(),
-                                            vec![
-                                                methods_tree,
-                                                properties_tree,
-                                                signals_tree,
-                                                // annotations_tree,
-                                            ],
-                                        )
-                                        .unwrap()
-                                    })
-                                    .collect();
-                                TreeItem::new(id, object_name.clone(), children).unwrap()
-                            })
-                            .collect(),
-                    );
+        terminal.draw(|frame| ui::<B>(frame, app))?;
+
+        // Take all pending messages, so progress updates don't hold up the results behind them
+        while let Ok(message) = app.dbus_rx.try_recv() {
+            match message {
+                AppMessage::Objects {
+                    service,
+                    nodes,
+                    complete,
+                } => {
+                    // Batches of a service that is no longer shown can still be on their way
+                    if service == app.objects_service {
+                        let paths: Vec<String> = nodes.keys().cloned().collect();
+                        app.nodes.extend(nodes);
+                        app.objects.add_nodes(&app.nodes, &paths, app.tree_options);
+                        app.walking = !complete;
+                        app.open_focus_interface(complete);
+                    }
+                }
+                AppMessage::ObjectsFailed { service, error } => {
+                    if service == app.objects_service {
+                        app.objects_error = Some(error);
+                    }
                 }
                 AppMessage::Services(names) => {
-                    app.services = StatefulList::with_items(names);
+                    app.request_sort_keys(names.clone()).await;
+                    app.all_services = names;
+                    app.services_listed = true;
+                    app.apply_service_filter();
+                    if let Some(service) = app.focus.service.take() {
+                        match app.services.items.iter().position(|name| *name == service) {
+                            Some(index) => app.services.state.select(Some(index)),
+                            None => app.status = Some(format!("{} is not on the bus", service)),
+                        }
+                    }
                 }
-            },
-            _error => (),
-        };
+                AppMessage::ServicesFailed(error) => {
+                    app.all_services.clear();
+                    app.services_listed = true;
+                    app.apply_service_filter();
+                    app.status = Some(format!("Could not list the services: {}", error));
+                }
+                AppMessage::Pids(pids) => {
+                    app.pids = pids;
+                    app.apply_service_filter();
+                }
+                AppMessage::Owners(owners) => {
+                    app.owners = owners;
+                    app.apply_service_filter();
+                }
+                AppMessage::Properties {
+                    path,
+                    interface,
+                    properties,
+                } => {
+                    app.properties_view = Some(PropertiesView {
+                        title: format!("{} on {}", interface, path),
+                        properties: properties
+                            .map(|properties| {
+                                properties
+                                    .into_iter()
+                                    .map(|(name, value)| (name, format_value(&value)))
+                                    .sorted()
+                                    .collect()
+                            })
+                            .map_err(CallError::from),
+                        scroll: 0,
+                        full: false,
+                    });
+                }
+                AppMessage::Snapshot {
+                    path,
+                    interface,
+                    properties,
+                    getters,
+                } => {
+                    // Whatever failed is listed with its error, so the rest of the snapshot is still shown
+                    let properties = match properties {
+                        Ok(properties) => properties
+                            .into_iter()
+                            .map(|(name, value)| (name, format_value(&value)))
+                            .sorted()
+                            .collect(),
+                        Err(err) => vec![("GetAll".to_string(), format!("failed: {}", err))],
+                    };
+                    let getters = getters.into_iter().map(|(method, result)| {
+                        let value = result.unwrap_or_else(|err| format!("failed: {}", err));
+                        (format!("{}()", method), value)
+                    });
+                    app.properties_view = Some(PropertiesView {
+                        title: format!("Snapshot of {} on {}", interface, path),
+                        properties: Ok(properties.into_iter().chain(getters).collect()),
+                        scroll: 0,
+                        full: false,
+                    });
+                }
+                // Methods are only called with --script for now
+                AppMessage::Reply(_) => {}
+            }
+        }
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => return Ok(()),
-                    KeyCode::Enter => match app.working_area {
-                        WorkingArea::Services => {
-                            if let Some(selected_index) = app.services.state.selected() {
-                                let item = app.services.items[selected_index].clone();
-                                app.dbus_handle.request_objects_from(item).await;
-                            }
+                app.status = None;
+                // Raw mode turns Ctrl+C into a key press, so quit on it from any state
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    return Ok(());
+                }
+                // Modal inputs get the keys before the working areas, so they can consume keys like Esc and q
+                if app.filtering {
+                    app.filter_key(key.code);
+                    continue;
+                }
+                let action = app.keymap.action(&key);
+                if let Some(view) = &mut app.properties_view {
+                    match (action, key.code) {
+                        // Like Esc, so quitting from a popup takes a second press
+                        (Some(Action::Quit), _) | (_, KeyCode::Esc) => app.properties_view = None,
+                        (Some(Action::Next), _) => view.scroll = view.scroll.saturating_add(1),
+                        (Some(Action::Previous), _) => {
+                            view.scroll = view.scroll.saturating_sub(1);
                         }
-                        WorkingArea::Objects => {
-                            //TOTO
+                        (_, KeyCode::Char('v')) => {
+                            view.full = !view.full;
+                            view.scroll = 0;
                         }
-                    },
-                    KeyCode::Left => match app.working_area {
-                        WorkingArea::Services => app.services.unselect(),
-                        WorkingArea::Objects => app.objects.left(),
-                    },
-                    KeyCode::Down => match app.working_area {
-                        WorkingArea::Services => app.services.next(),
-                        WorkingArea::Objects => app.objects.down(),
-                    },
-                    KeyCode::Up => match app.working_area {
-                        WorkingArea::Services => app.services.previous(),
-                        WorkingArea::Objects => app.objects.up(),
-                    },
-                    KeyCode::Right => match app.working_area {
-                        WorkingArea::Services => {}
-                        WorkingArea::Objects => app.objects.right(),
-                    },
-                    KeyCode::Tab => match app.working_area {
-                        WorkingArea::Services => app.working_area = WorkingArea::Objects,
-                        WorkingArea::Objects => app.working_area = WorkingArea::Services,
-                    },
+                        (_, KeyCode::Char('+')) => app.resize_popup(10),
+                        (_, KeyCode::Char('-')) => app.resize_popup(-10),
+                        _ => (),
+                    }
+                    continue;
+                }
+                if app.show_help {
+                    if let (Some(Action::Quit), _) | (_, KeyCode::Esc | KeyCode::Char('?')) =
+                        (action, key.code)
+                    {
+                        app.show_help = false;
+                    }
+                    continue;
+                }
+                // Keys bound to an action in the keys file are used up by it, even where it does nothing
+                if let Some(action) = action {
+                    match action {
+                        Action::Quit => return Ok(()),
+                        Action::Search => {
+                            app.filtering = app.working_area == WorkingArea::Services;
+                        }
+                        Action::Select => match app.working_area {
+                            WorkingArea::Services => {
+                                if let Some(selected_index) = app.services.state.selected() {
+                                    let item = app.services.items[selected_index].clone();
+                                    app.request_objects(item, root_path()).await;
+                                }
+                            }
+                            WorkingArea::Objects => {
+                                //TOTO
+                            }
+                        },
+                        Action::Collapse => match app.working_area {
+                            WorkingArea::Services => app.services.unselect(),
+                            WorkingArea::Objects => app.objects.left(),
+                        },
+                        Action::Next => match (app.navigation_step(key.code), &app.working_area) {
+                            (1, WorkingArea::Services) => app.services.next(),
+                            (step, WorkingArea::Services) => app.services.down_by(step),
+                            (1, WorkingArea::Objects) => app.objects.down(),
+                            (step, WorkingArea::Objects) => app.objects.down_by(step),
+                        },
+                        Action::Previous => {
+                            match (app.navigation_step(key.code), &app.working_area) {
+                                (1, WorkingArea::Services) => app.services.previous(),
+                                (step, WorkingArea::Services) => app.services.up_by(step),
+                                (1, WorkingArea::Objects) => app.objects.up(),
+                                (step, WorkingArea::Objects) => app.objects.up_by(step),
+                            }
+                        }
+                        Action::Expand => match app.working_area {
+                            WorkingArea::Services => {}
+                            WorkingArea::Objects => app.objects.right(),
+                        },
+                        Action::FocusNext => match app.working_area {
+                            WorkingArea::Services => app.working_area = WorkingArea::Objects,
+                            WorkingArea::Objects => app.working_area = WorkingArea::Services,
+                        },
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::PageDown => app.page_down(),
+                    KeyCode::PageUp => app.page_up(),
+                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.page_down();
+                    }
+                    KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.page_up();
+                    }
+                    KeyCode::Char('g') if app.working_area == WorkingArea::Objects => {
+                        app.request_all_properties().await;
+                    }
+                    KeyCode::Char('G') if app.working_area == WorkingArea::Objects => {
+                        app.request_snapshot().await;
+                    }
+                    KeyCode::Char(']') if app.working_area == WorkingArea::Objects => {
+                        app.objects.next_interface();
+                    }
+                    KeyCode::Char('[') if app.working_area == WorkingArea::Objects => {
+                        app.objects.previous_interface();
+                    }
+                    KeyCode::Char('p') if app.working_area == WorkingArea::Services => {
+                        app.toggle_service_sort(ServiceSort::Pid).await;
+                    }
+                    KeyCode::Char('a') if app.working_area == WorkingArea::Services => {
+                        app.toggle_service_sort(ServiceSort::Owner).await;
+                    }
+                    KeyCode::Char('*') if app.working_area == WorkingArea::Services => {
+                        app.toggle_favorite();
+                    }
+                    KeyCode::Char('e') => app.export_markdown(),
+                    KeyCode::Char('c') => app.copy_address(),
+                    KeyCode::Char('f') => app.toggle_flatten_members(),
+                    KeyCode::Char('o') => app.toggle_sort_members(),
+                    KeyCode::Char('b') => app.toggle_indent_paths(),
+                    KeyCode::Char('d') => app.toggle_explicit_directions(),
+                    KeyCode::Char('t') => app.toggle_explain_signatures(),
+                    KeyCode::Char('D') => app.dense = !app.dense,
+                    KeyCode::Char('I') => app.toggle_standard_interfaces(),
+                    KeyCode::Char('s') => app.toggle_hide_standard_interfaces(),
+                    KeyCode::Char('?') => app.show_help = true,
+                    KeyCode::Char('i') if app.implements.is_some() => {
+                        app.filter_implements = !app.filter_implements;
+                        app.request_services().await;
+                    }
                     _ => (),
                 }
             }
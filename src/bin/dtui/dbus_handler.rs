@@ -1,89 +1,457 @@
-use std::{collections::HashMap, error::Error, io::BufReader};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io::BufReader,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use async_recursion::async_recursion;
+use dtui::stateful_tree::Nodes;
+use itertools::Itertools;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use zbus::{names::OwnedBusName, zvariant::ObjectPath, Connection};
+use zbus::{
+    names::{BusName, OwnedBusName, OwnedInterfaceName},
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Structure, StructureBuilder, Value},
+    Connection,
+};
 use zbus_xml::Node;
 
-use crate::messages::{AppMessage, DbusMessage};
+use crate::{
+    format::format_value,
+    messages::{AppMessage, DbusMessage},
+};
+
+// How often objects found while walking a service are sent to the app
+const BATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long walking a service took, with the time each object took to introspect
+pub struct WalkProfile {
+    pub service: String,
+    pub total: Duration,
+    pub objects: Vec<(String, Duration)>,
+    // Why the walk failed, if its first object could not be introspected
+    pub error: Option<String>,
+}
+
+/// Walks are added to it as they are done, given with --profile
+pub type Profile = Arc<Mutex<Vec<WalkProfile>>>;
 
 pub struct DbusActor {
     app_sender: Sender<AppMessage>,
     app_receiver: Receiver<DbusMessage>,
     connection: Connection,
+    // Interfaces found in each service when filtering on them, introspecting a whole service is slow
+    interface_cache: HashMap<OwnedBusName, HashSet<String>>,
+    // Process id of the owner of each name, None if the bus could not tell
+    pid_cache: HashMap<OwnedBusName, Option<u32>>,
+    // Whether to walk the objects below /, or only introspect / itself
+    recurse: bool,
+    profile: Option<Profile>,
 }
 impl DbusActor {
     pub fn new(
         app_sender: Sender<AppMessage>,
         app_receiver: Receiver<DbusMessage>,
         connection: Connection,
+        recurse: bool,
+        profile: Option<Profile>,
     ) -> Self {
         Self {
             app_sender,
             app_receiver,
             connection,
+            interface_cache: HashMap::new(),
+            pid_cache: HashMap::new(),
+            recurse,
+            profile,
         }
     }
-    async fn get_node(
+    // service_name is None when talking directly to a peer, as there is no bus to route the call
+    async fn introspect(
         &self,
-        service_name: &OwnedBusName,
+        service_name: Option<&OwnedBusName>,
         path: &ObjectPath<'_>,
-    ) -> Result<Node<'static>, Box<dyn Error + Sync + Send>> {
-        let introspectable_proxy = zbus::fdo::IntrospectableProxy::builder(&self.connection)
-            .destination(service_name)?
-            .path(path.clone())?
-            .build()
+    ) -> Result<String, Box<dyn Error + Sync + Send>> {
+        let reply = self
+            .connection
+            .call_method(
+                service_name,
+                path,
+                Some("org.freedesktop.DBus.Introspectable"),
+                "Introspect",
+                &(),
+            )
             .await?;
-        let introspect_xml: String = introspectable_proxy.introspect().await?;
-        let introspect = Node::from_reader(BufReader::new(introspect_xml.as_bytes()))?;
-        Ok(introspect)
+        Ok(reply.body().deserialize()?)
     }
 
-    #[async_recursion]
+    // Walks the object tree below path, or only introspects path itself without recurse. With stream the objects
+    // are sent to the app in batches while walking, and only the objects found since the last batch are returned.
+    // Objects below path that fail are kept with the error, only failing to introspect path itself is an error
     async fn get_sub_nodes(
         &self,
-        service_name: &OwnedBusName,
-        path: &ObjectPath<'async_recursion>,
-    ) -> Result<HashMap<String, Node<'static>>, Box<dyn Error + Send + Sync>> {
+        service_name: Option<&OwnedBusName>,
+        path: &ObjectPath<'_>,
+        recurse: bool,
+        stream: bool,
+    ) -> Result<Nodes, Box<dyn Error + Send + Sync>> {
         let mut result = HashMap::new();
-        let node = self.get_node(service_name, path).await?;
-
-        for sub_node in node.nodes() {
-            if let Some(name) = sub_node.name() {
-                let path_name = if path.as_str().ends_with('/') {
-                    path.as_str().to_string() + name
-                } else {
-                    path.as_str().to_string() + "/" + name
-                };
-                let sub_path = ObjectPath::try_from(path_name)?;
-                result.extend(self.get_sub_nodes(service_name, &sub_path).await?)
+        let start = path.as_str();
+        let mut paths = vec![path.to_owned()];
+        let mut last_batch = Instant::now();
+        let started = Instant::now();
+        let mut timings = Vec::new();
+        while let Some(path) = paths.pop() {
+            let introspected = Instant::now();
+            let reply = self.introspect(service_name, &path).await;
+            if self.profile.is_some() {
+                timings.push((path.to_string(), introspected.elapsed()));
+            }
+            let introspect_xml = match reply {
+                Ok(introspect_xml) => introspect_xml,
+                Err(err) if path.as_str() == start => {
+                    let error = introspection_error(&*err);
+                    self.record_walk(service_name, started, timings, Some(error));
+                    return Err(err);
+                }
+                Err(err) => {
+                    result.insert(path.to_string(), Err(introspection_error(&*err)));
+                    continue;
+                }
+            };
+            // Keep the parse error, so that the object still shows up with what went wrong
+            match Node::from_reader(BufReader::new(introspect_xml.as_bytes())) {
+                Ok(node) => {
+                    let sub_nodes = if recurse { node.nodes() } else { &[] };
+                    for sub_node in sub_nodes {
+                        if let Some(name) = sub_node.name() {
+                            let path_name = if path.as_str().ends_with('/') {
+                                path.as_str().to_string() + name
+                            } else {
+                                path.as_str().to_string() + "/" + name
+                            };
+                            match ObjectPath::try_from(path_name.as_str()) {
+                                Ok(sub_path) => paths.push(sub_path.into_owned()),
+                                Err(err) => {
+                                    result.insert(path_name, Err(err.to_string()));
+                                }
+                            }
+                        }
+                    }
+                    result.insert(path.to_string(), Ok(node));
+                }
+                Err(err) => {
+                    let err = format!("Invalid introspection data: {}", err);
+                    result.insert(path.to_string(), Err(err));
+                }
+            }
+            if stream && last_batch.elapsed() >= BATCH_INTERVAL {
+                let _ = self
+                    .app_sender
+                    .send(AppMessage::Objects {
+                        service: service_name.cloned(),
+                        nodes: std::mem::take(&mut result),
+                        complete: false,
+                    })
+                    .await;
+                last_batch = Instant::now();
             }
         }
-        result.insert(path.to_string(), node);
+        self.record_walk(service_name, started, timings, None);
         Ok(result)
     }
 
+    // Adds a walk to the profile when profiling, whether it got through or not
+    fn record_walk(
+        &self,
+        service_name: Option<&OwnedBusName>,
+        started: Instant,
+        objects: Vec<(String, Duration)>,
+        error: Option<String>,
+    ) {
+        if let Some(profile) = &self.profile {
+            profile.lock().unwrap().push(WalkProfile {
+                service: service_name.map_or("peer".to_string(), |name| name.to_string()),
+                total: started.elapsed(),
+                objects,
+                error,
+            });
+        }
+    }
+
+    // Sends the objects of a service in batches while walking it, the last one marked complete.
+    // Only the objects shown are limited by --no-recurse
+    async fn stream_objects(&self, service_name: Option<OwnedBusName>, path: &ObjectPath<'_>) {
+        // Objects that fail are in the tree with their error, the walk only fails when path itself does
+        let walked = self
+            .get_sub_nodes(service_name.as_ref(), path, self.recurse, true)
+            .await;
+        let nodes = match walked {
+            Ok(nodes) => nodes,
+            Err(err) => {
+                let _ = self
+                    .app_sender
+                    .send(AppMessage::ObjectsFailed {
+                        service: service_name.clone(),
+                        error: introspection_error(&*err),
+                    })
+                    .await;
+                Nodes::new()
+            }
+        };
+        self.app_sender
+            .send(AppMessage::Objects {
+                service: service_name,
+                nodes,
+                complete: true,
+            })
+            .await
+            .expect("channel dead");
+    }
+
+    // The interface can be on any object, so the whole service is walked even with --no-recurse
+    async fn implements(&mut self, service_name: &OwnedBusName, interface: &str) -> bool {
+        if !self.interface_cache.contains_key(service_name) {
+            let path = ObjectPath::try_from("/").expect("/ is always a valid path");
+            let interfaces = match self
+                .get_sub_nodes(Some(service_name), &path, true, false)
+                .await
+            {
+                Ok(nodes) => nodes
+                    .values()
+                    .flatten()
+                    .flat_map(|node| node.interfaces())
+                    .map(|interface| interface.name().to_string())
+                    .collect(),
+                Err(_) => HashSet::new(),
+            };
+            self.interface_cache
+                .insert(service_name.clone(), interfaces);
+        }
+        self.interface_cache[service_name].contains(interface)
+    }
+
+    async fn list_names(
+        &mut self,
+        implements: Option<String>,
+    ) -> Result<Vec<OwnedBusName>, zbus::Error> {
+        let proxy = zbus::fdo::DBusProxy::new(&self.connection).await?;
+        let names = proxy.list_names().await?;
+        let Some(interface) = implements else {
+            return Ok(names);
+        };
+        let mut implementing = Vec::new();
+        let own_name = self.connection.unique_name().map(|name| name.to_string());
+        for name in names {
+            // Walking our own connection would wait on ourselves until the call times out
+            if own_name.as_deref() == Some(name.as_str()) {
+                continue;
+            }
+            // Names owned by the same connection share its objects, so each connection is only walked once
+            let owner = match &*name {
+                BusName::Unique(_) => name.clone(),
+                BusName::WellKnown(_) => match proxy.get_name_owner(name.as_ref()).await {
+                    Ok(owner) => BusName::from(&owner).into(),
+                    Err(_) => name.clone(),
+                },
+            };
+            if self.implements(&owner, &interface).await {
+                implementing.push(name);
+            }
+        }
+        Ok(implementing)
+    }
+
+    async fn pid(&mut self, service_name: &OwnedBusName) -> Option<u32> {
+        if !self.pid_cache.contains_key(service_name) {
+            let pid = match zbus::fdo::DBusProxy::new(&self.connection).await {
+                Ok(proxy) => proxy
+                    .get_connection_unix_process_id(service_name.as_ref())
+                    .await
+                    .ok(),
+                Err(_) => None,
+            };
+            self.pid_cache.insert(service_name.clone(), pid);
+        }
+        self.pid_cache[service_name]
+    }
+
+    async fn get_all_properties(
+        &self,
+        service_name: Option<OwnedBusName>,
+        path: &OwnedObjectPath,
+        interface: &OwnedInterfaceName,
+    ) -> Result<HashMap<String, OwnedValue>, zbus::Error> {
+        let reply = self
+            .connection
+            .call_method(
+                service_name,
+                path,
+                Some("org.freedesktop.DBus.Properties"),
+                "GetAll",
+                &(interface,),
+            )
+            .await?;
+        reply.body().deserialize()
+    }
+
+    // Calls a method with the given arguments, returning the values it replies with
+    async fn call_method(
+        &self,
+        service_name: Option<&OwnedBusName>,
+        path: &OwnedObjectPath,
+        interface: &OwnedInterfaceName,
+        method: &str,
+        args: Vec<OwnedValue>,
+    ) -> Result<Vec<OwnedValue>, zbus::Error> {
+        let connection = &self.connection;
+        // The fields of a structure are sent as the arguments, but a structure can't be empty
+        let reply = if args.is_empty() {
+            connection
+                .call_method(service_name, path, Some(interface), method, &())
+                .await?
+        } else {
+            let args = args
+                .into_iter()
+                .fold(StructureBuilder::new(), |args, arg| {
+                    args.append_field(Value::from(arg))
+                })
+                .build();
+            connection
+                .call_method(service_name, path, Some(interface), method, &args)
+                .await?
+        };
+        let body = reply.body();
+        if body
+            .signature()
+            .map_or(true, |signature| signature.is_empty())
+        {
+            return Ok(Vec::new());
+        }
+        let values: Structure = body.deserialize()?;
+        values
+            .into_fields()
+            .into_iter()
+            .map(|value| OwnedValue::try_from(value).map_err(zbus::Error::from))
+            .collect()
+    }
+
+    // Calls a method without arguments, formatting the values it returns
+    async fn call_getter(
+        &self,
+        service_name: Option<&OwnedBusName>,
+        path: &OwnedObjectPath,
+        interface: &OwnedInterfaceName,
+        method: &str,
+    ) -> Result<String, zbus::Error> {
+        let values = self
+            .call_method(service_name, path, interface, method, Vec::new())
+            .await?;
+        Ok(values.iter().map(|value| format_value(value)).join(", "))
+    }
+
     pub async fn handle_message(&mut self, msg: DbusMessage) {
         match msg {
-            DbusMessage::GetObjects(service_name) => {
-                let path_name = "/".to_string();
-                let path = ObjectPath::try_from(path_name).expect("/ is always a valid path");
-                if let Ok(nodes) = self.get_sub_nodes(&service_name, &path).await {
-                    self.app_sender
-                        .send(AppMessage::Objects(nodes))
-                        .await
-                        .expect("channel dead");
+            DbusMessage::GetObjects(service_name, path) => {
+                self.stream_objects(service_name, &path).await;
+            }
+            DbusMessage::GetAllProperties(service_name, path, interface) => {
+                let properties = self
+                    .get_all_properties(service_name, &path, &interface)
+                    .await;
+                let _ = self
+                    .app_sender
+                    .send(AppMessage::Properties {
+                        path,
+                        interface,
+                        properties,
+                    })
+                    .await;
+            }
+            DbusMessage::GetSnapshot {
+                service,
+                path,
+                interface,
+                getters,
+            } => {
+                let properties = self
+                    .get_all_properties(service.clone(), &path, &interface)
+                    .await;
+                let mut results = Vec::new();
+                for getter in getters {
+                    let result = self
+                        .call_getter(service.as_ref(), &path, &interface, &getter)
+                        .await;
+                    results.push((getter, result));
                 }
+                let _ = self
+                    .app_sender
+                    .send(AppMessage::Snapshot {
+                        path,
+                        interface,
+                        properties,
+                        getters: results,
+                    })
+                    .await;
             }
-            DbusMessage::ServiceRequest() => {
-                let proxy = zbus::fdo::DBusProxy::new(&self.connection)
-                    .await
-                    .expect("Could not create DbusProxy");
-                if let Ok(names) = proxy.list_names().await {
-                    let _ = self.app_sender.send(AppMessage::Services(names)).await;
+            DbusMessage::CallMethod {
+                service,
+                path,
+                interface,
+                method,
+                args,
+            } => {
+                let reply = self
+                    .call_method(service.as_ref(), &path, &interface, &method, args)
+                    .await;
+                let _ = self.app_sender.send(AppMessage::Reply(reply)).await;
+            }
+            DbusMessage::GetPids(names) => {
+                let mut pids = HashMap::new();
+                for name in names {
+                    if let Some(pid) = self.pid(&name).await {
+                        pids.insert(name, pid);
+                    }
+                }
+                let _ = self.app_sender.send(AppMessage::Pids(pids)).await;
+            }
+            DbusMessage::GetOwners(names) => {
+                let mut owners = HashMap::new();
+                if let Ok(proxy) = zbus::fdo::DBusProxy::new(&self.connection).await {
+                    for name in names {
+                        if let Ok(owner) = proxy.get_name_owner(name.as_ref()).await {
+                            owners.insert(name, owner);
+                        }
+                    }
                 }
+                let _ = self.app_sender.send(AppMessage::Owners(owners)).await;
             }
+            DbusMessage::ServiceRequest(_) if !self.connection.is_bus() => {
+                // A peer has no bus to list names on, so show its objects right away
+                let path = ObjectPath::try_from("/").expect("/ is always a valid path");
+                self.stream_objects(None, &path).await;
+            }
+            DbusMessage::ServiceRequest(implements) => {
+                let message = match self.list_names(implements).await {
+                    Ok(names) => AppMessage::Services(names),
+                    Err(err) => AppMessage::ServicesFailed(err.to_string()),
+                };
+                let _ = self.app_sender.send(message).await;
+            }
+        }
+    }
+}
+
+// Services without the Introspectable interface answer with one of these errors
+fn introspection_error(err: &(dyn Error + 'static)) -> String {
+    match err.downcast_ref::<zbus::Error>() {
+        Some(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.UnknownMethod"
+                || name.as_str() == "org.freedesktop.DBus.Error.UnknownInterface" =>
+        {
+            "Does not support introspection".to_string()
         }
+        _ => format!("Could not introspect: {}", err),
     }
 }
 
@@ -99,21 +467,80 @@ pub struct DbusActorHandle {
 }
 
 impl DbusActorHandle {
-    pub fn new(app_sender: Sender<AppMessage>, connection: Connection) -> Self {
+    pub fn new(
+        app_sender: Sender<AppMessage>,
+        connection: Connection,
+        recurse: bool,
+        profile: Option<Profile>,
+    ) -> Self {
         let (sender, receiver) = mpsc::channel(8);
-        let actor = DbusActor::new(app_sender, receiver, connection);
+        let actor = DbusActor::new(app_sender, receiver, connection, recurse, profile);
         tokio::spawn(run_actor(actor));
 
         Self { sender }
     }
 
-    pub async fn request_objects_from(&self, object: OwnedBusName) {
-        let msg = DbusMessage::GetObjects(object);
+    pub async fn request_objects_from(&self, object: Option<OwnedBusName>, path: OwnedObjectPath) {
+        let msg = DbusMessage::GetObjects(object, path);
+        let _ = self.sender.send(msg).await;
+    }
+
+    pub async fn request_all_properties(
+        &self,
+        service: Option<OwnedBusName>,
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+    ) {
+        let msg = DbusMessage::GetAllProperties(service, path, interface);
+        let _ = self.sender.send(msg).await;
+    }
+
+    pub async fn request_snapshot(
+        &self,
+        service: Option<OwnedBusName>,
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        getters: Vec<String>,
+    ) {
+        let msg = DbusMessage::GetSnapshot {
+            service,
+            path,
+            interface,
+            getters,
+        };
+        let _ = self.sender.send(msg).await;
+    }
+
+    pub async fn request_call(
+        &self,
+        service: Option<OwnedBusName>,
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        method: String,
+        args: Vec<OwnedValue>,
+    ) {
+        let msg = DbusMessage::CallMethod {
+            service,
+            path,
+            interface,
+            method,
+            args,
+        };
+        let _ = self.sender.send(msg).await;
+    }
+
+    pub async fn request_pids(&self, names: Vec<OwnedBusName>) {
+        let msg = DbusMessage::GetPids(names);
+        let _ = self.sender.send(msg).await;
+    }
+
+    pub async fn request_owners(&self, names: Vec<OwnedBusName>) {
+        let msg = DbusMessage::GetOwners(names);
         let _ = self.sender.send(msg).await;
     }
 
-    pub async fn request_services(&self) {
-        let msg = DbusMessage::ServiceRequest();
+    pub async fn request_services(&self, implements: Option<String>) {
+        let msg = DbusMessage::ServiceRequest(implements);
         let _ = self.sender.send(msg).await;
     }
 }
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use zbus::zvariant::{self, Basic, Dict, ObjectPath, OwnedValue, Signature, Str, Value};
+
+/// Formats a value for reading, like its Display but with byte arrays as hex.
+/// Arrays, structures and dicts are formatted element by element, so bytes nested in them are hex too
+pub fn format_value(value: &Value) -> String {
+    match value {
+        // Display marks numbers with their type, like uint32 42, which the signature already tells
+        Value::U8(value) => value.to_string(),
+        Value::Bool(value) => value.to_string(),
+        Value::I16(value) => value.to_string(),
+        Value::U16(value) => value.to_string(),
+        Value::I32(value) => value.to_string(),
+        Value::U32(value) => value.to_string(),
+        Value::I64(value) => value.to_string(),
+        Value::U64(value) => value.to_string(),
+        Value::F64(value) => value.to_string(),
+        Value::Value(value) => format_value(value),
+        Value::Array(array) if array.element_signature() == "y" => {
+            let bytes = array
+                .inner()
+                .iter()
+                .map(|byte| match byte {
+                    Value::U8(byte) => format!("{:02x}", byte),
+                    other => other.to_string(),
+                })
+                .join(" ");
+            format!("[{}]", bytes)
+        }
+        Value::Array(array) => format!("[{}]", array.inner().iter().map(format_value).join(", ")),
+        Value::Structure(structure) => {
+            format!(
+                "({})",
+                structure.fields().iter().map(format_value).join(", ")
+            )
+        }
+        Value::Dict(dict) => format_dict(dict).unwrap_or_else(|| dict.to_string()),
+        value => value.to_string(),
+    }
+}
+
+// Dicts can only be taken apart by converting them to a map, which needs the type of their keys.
+// Keys that can't be ordered, like doubles, are left to Display
+fn format_dict(dict: &Dict) -> Option<String> {
+    fn entries<'k, K>(dict: Dict<'k, '_>) -> Option<Vec<(Value<'k>, OwnedValue)>>
+    where
+        K: Basic + Ord + TryFrom<Value<'k>> + Into<Value<'k>>,
+        K::Error: Into<zvariant::Error>,
+    {
+        let map = BTreeMap::<K, OwnedValue>::try_from(dict).ok()?;
+        Some(
+            map.into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+    let key = dict.full_signature().as_bytes().get(2).copied();
+    let dict = dict.try_clone().ok()?;
+    let entries = match key? {
+        b'y' => entries::<u8>(dict),
+        b'b' => entries::<bool>(dict),
+        b'n' => entries::<i16>(dict),
+        b'q' => entries::<u16>(dict),
+        b'i' => entries::<i32>(dict),
+        b'u' => entries::<u32>(dict),
+        b'x' => entries::<i64>(dict),
+        b't' => entries::<u64>(dict),
+        b's' => entries::<Str>(dict),
+        b'o' => entries::<ObjectPath>(dict),
+        b'g' => entries::<Signature>(dict),
+        _ => None,
+    }?;
+    let entries = entries
+        .iter()
+        .map(|(key, value)| format!("{}: {}", format_value(key), format_value(value)))
+        .join(", ");
+    Some(format!("{{{}}}", entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn values_are_formatted_without_type_annotations() {
+        let bytes = Value::from(vec![0xdeu8, 0xad]);
+        let dict = HashMap::from([
+            ("n", Value::from(42u32)),
+            ("b", Value::from(vec![0xdeu8, 0xad])),
+        ]);
+        let numbered = HashMap::from([(2u32, "two"), (1u32, "one")]);
+        let nested = HashMap::from([("inner", Value::from(HashMap::from([(7u8, true)])))]);
+        let cases = [
+            (Value::from(42u32), "42"),
+            (Value::from("text"), "\"text\""),
+            (bytes, "[de ad]"),
+            (Value::from(vec![1i32, 2]), "[1, 2]"),
+            (Value::from((1u8, "one")), "(1, \"one\")"),
+            (Value::from(dict), "{\"b\": [de ad], \"n\": 42}"),
+            (Value::from(numbered), "{1: \"one\", 2: \"two\"}"),
+            (Value::from(nested), "{\"inner\": {7: true}}"),
+            (Value::from(HashMap::<String, u32>::new()), "{}"),
+        ];
+        for (value, formatted) in cases {
+            assert_eq!(format_value(&value), formatted);
+        }
+    }
+}
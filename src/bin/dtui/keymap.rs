@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use itertools::Itertools;
+
+/// Things the user can do with a key that can be bound to another one in the keys file
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    Quit,
+    Select,
+    Next,
+    Previous,
+    Collapse,
+    Expand,
+    FocusNext,
+    Search,
+}
+
+impl Action {
+    const ALL: [Action; 8] = [
+        Action::Quit,
+        Action::Select,
+        Action::Next,
+        Action::Previous,
+        Action::Collapse,
+        Action::Expand,
+        Action::FocusNext,
+        Action::Search,
+    ];
+
+    /// The name of the action in the keys file
+    fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Select => "select",
+            Action::Next => "next",
+            Action::Previous => "previous",
+            Action::Collapse => "collapse",
+            Action::Expand => "expand",
+            Action::FocusNext => "focus-next",
+            Action::Search => "search",
+        }
+    }
+
+    fn default_key(self) -> KeyCode {
+        match self {
+            Action::Quit => KeyCode::Char('q'),
+            Action::Select => KeyCode::Enter,
+            Action::Next => KeyCode::Down,
+            Action::Previous => KeyCode::Up,
+            Action::Collapse => KeyCode::Left,
+            Action::Expand => KeyCode::Right,
+            Action::FocusNext => KeyCode::Tab,
+            Action::Search => KeyCode::Char('/'),
+        }
+    }
+}
+
+// Keys that do something that can't be bound to another key, binding them would make it unreachable.
+// v, + and - are only used in the properties popup
+const RESERVED: [(KeyCode, &str); 24] = [
+    (KeyCode::PageUp, "page up"),
+    (KeyCode::PageDown, "page down"),
+    (KeyCode::Char('['), "previous interface"),
+    (KeyCode::Char(']'), "next interface"),
+    (KeyCode::Char('p'), "sort services by PID"),
+    (KeyCode::Char('a'), "group services by owner"),
+    (KeyCode::Char('*'), "favorite service"),
+    (KeyCode::Char('g'), "get all properties"),
+    (KeyCode::Char('G'), "snapshot properties and getters"),
+    (KeyCode::Char('e'), "export to markdown"),
+    (KeyCode::Char('c'), "copy address"),
+    (KeyCode::Char('f'), "flatten members"),
+    (KeyCode::Char('o'), "sort members"),
+    (KeyCode::Char('b'), "indent paths"),
+    (KeyCode::Char('d'), "argument directions"),
+    (KeyCode::Char('t'), "explain types"),
+    (KeyCode::Char('I'), "implicit interfaces"),
+    (KeyCode::Char('s'), "hide standard interfaces"),
+    (KeyCode::Char('D'), "dense"),
+    (KeyCode::Char('?'), "help"),
+    (KeyCode::Char('i'), "filter on --implements"),
+    (KeyCode::Char('v'), "full values"),
+    (KeyCode::Char('+'), "widen popup"),
+    (KeyCode::Char('-'), "narrow popup"),
+];
+
+pub struct KeyMap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            bindings: Action::ALL
+                .iter()
+                .map(|action| (action.default_key(), *action))
+                .collect(),
+        }
+    }
+}
+
+impl KeyMap {
+    /// Read bindings like `next = j`, one per line. Actions that are bound lose their default key,
+    /// the others keep it. A key can only be bound to one action, and not at all if it has a fixed use.
+    /// Empty lines and lines starting with # are skipped
+    pub fn parse(contents: &str) -> Result<KeyMap, String> {
+        let mut bound = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let Some((name, key)) = line.split_once('=') else {
+                return Err(error(format!("expected action = key, got {}", line)));
+            };
+            let (name, key) = (name.trim(), key.trim());
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| error(format!("unknown action {}", name)))?;
+            let key = parse_key(key).ok_or_else(|| error(format!("unknown key {}", key)))?;
+            if let Some((_, used)) = RESERVED.iter().find(|(reserved, _)| *reserved == key) {
+                let name = key_name(key);
+                return Err(error(format!("{} is already bound to {}", name, used)));
+            }
+            bound.push((number + 1, action, key));
+        }
+        // Defaults are dropped first, so keys can be swapped between actions in any order
+        let mut keymap = KeyMap::default();
+        keymap
+            .bindings
+            .retain(|_, action| !bound.iter().any(|(_, bound, _)| bound == action));
+        for (number, action, key) in bound {
+            if let Some(other) = keymap.bindings.insert(key, action) {
+                if other != action {
+                    return Err(format!(
+                        "line {}: {} is already bound to {}",
+                        number,
+                        key_name(key),
+                        other.name()
+                    ));
+                }
+            }
+        }
+        Ok(keymap)
+    }
+
+    /// The keys bound to an action as shown in the help, or "none" if it has no key
+    pub fn keys(&self, action: Action) -> String {
+        let keys = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| key_name(*key))
+            .sorted()
+            .join("/");
+        if keys.is_empty() {
+            "none".to_string()
+        } else {
+            keys
+        }
+    }
+
+    /// The action bound to a key, if any. Keys with Ctrl held are never bound
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return None;
+        }
+        self.bindings.get(&key.code).copied()
+    }
+}
+
+// How a key is shown in the help
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        KeyCode::PageUp => "PgUp".to_string(),
+        KeyCode::PageDown => "PgDn".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+// A single character, or the name of a special key like Enter or PageDown
+fn parse_key(key: &str) -> Option<KeyCode> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    Some(match key.to_lowercase().as_str() {
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "esc" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn bound_actions_lose_their_default_key() {
+        let keymap = KeyMap::parse("next = j\n# comment\n\nprevious = k").unwrap();
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('j'))),
+            Some(Action::Next)
+        );
+        assert_eq!(keymap.action(&press(KeyCode::Down)), None);
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('q'))),
+            Some(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn keys_can_be_swapped_in_any_order() {
+        let keymap = KeyMap::parse("search = q\nquit = /").unwrap();
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('q'))),
+            Some(Action::Search)
+        );
+        assert_eq!(
+            keymap.action(&press(KeyCode::Char('/'))),
+            Some(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn taking_the_key_of_another_action_is_refused() {
+        assert_eq!(
+            KeyMap::parse("search = q").err(),
+            Some("line 1: q is already bound to quit".to_string())
+        );
+        assert_eq!(
+            KeyMap::parse("next = j\nprevious = j").err(),
+            Some("line 2: j is already bound to next".to_string())
+        );
+    }
+
+    #[test]
+    fn keys_with_a_fixed_use_are_refused() {
+        assert_eq!(
+            KeyMap::parse("select = e").err(),
+            Some("line 1: e is already bound to export to markdown".to_string())
+        );
+        assert_eq!(
+            KeyMap::parse("next = j\nquit = v").err(),
+            Some("line 2: v is already bound to full values".to_string())
+        );
+        assert_eq!(
+            KeyMap::parse("next = PageDown").err(),
+            Some("line 1: PgDn is already bound to page down".to_string())
+        );
+    }
+}
@@ -1,74 +1,592 @@
 pub mod app;
+pub mod clipboard;
 pub mod dbus_handler;
+pub mod format;
+pub mod keymap;
+pub mod markdown;
 pub mod messages;
+pub mod script;
 pub mod stateful_list;
-pub mod stateful_tree;
 pub mod ui;
 
-use app::{run_app, App};
-use clap::{command, ArgGroup, Parser, ValueEnum};
+use app::{run_app, App, Focus};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use dbus_handler::DbusActorHandle;
+use dbus_handler::{DbusActorHandle, Profile, WalkProfile};
 
+use itertools::Itertools;
+use keymap::{Action, KeyMap};
 use messages::AppMessage;
 
 use ratatui::{
     backend::{Backend, CrosstermBackend},
+    style::{Modifier, Style},
     Terminal,
 };
-use std::{error::Error, io, time::Duration};
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::sync::mpsc::{self};
 
-use zbus::{Connection, ConnectionBuilder};
+use zbus::{
+    names::OwnedBusName, zvariant::OwnedObjectPath, AuthMechanism, Connection, ConnectionBuilder,
+};
+use zbus_xml::Node;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum BusType {
     System,
     Session,
 }
+#[derive(Copy, Clone, ValueEnum)]
+enum HighlightStyle {
+    Bold,
+    Reverse,
+    Underline,
+}
+
+impl From<HighlightStyle> for Style {
+    fn from(style: HighlightStyle) -> Self {
+        let modifier = match style {
+            HighlightStyle::Bold => Modifier::BOLD,
+            HighlightStyle::Reverse => Modifier::REVERSED,
+            HighlightStyle::Underline => Modifier::UNDERLINED,
+        };
+        Style::default().add_modifier(modifier)
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum Auth {
+    External,
+    Cookie,
+    Anonymous,
+}
+
+impl From<Auth> for AuthMechanism {
+    fn from(auth: Auth) -> Self {
+        match auth {
+            Auth::External => AuthMechanism::External,
+            Auth::Cookie => AuthMechanism::Cookie,
+            Auth::Anonymous => AuthMechanism::Anonymous,
+        }
+    }
+}
+
+// The bus to connect to when none is given, DTUI_DEFAULT_BUS can change it from the system bus
+fn default_bus() -> BusType {
+    match std::env::var("DTUI_DEFAULT_BUS") {
+        Ok(bus) => BusType::from_str(&bus, true).unwrap_or_else(|_| {
+            eprintln!(
+                "Ignoring DTUI_DEFAULT_BUS={}, it should be system or session",
+                bus
+            );
+            BusType::System
+        }),
+        Err(_) => BusType::System,
+    }
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-#[clap(group(ArgGroup::new("bus_or_address").args(&["bus", "address"])))]
+#[clap(group(ArgGroup::new("bus_or_address").args(&["bus", "address", "machine", "user"])))]
 struct Args {
-    //Which bus to connect to
-    #[clap(default_value_t = BusType::System)]
+    /// Which bus to connect to, the system bus unless DTUI_DEFAULT_BUS says otherwise
     #[arg(value_enum)]
-    bus: BusType,
+    bus: Option<BusType>,
 
-    //Address of potentially remote connection
+    /// Address of potentially remote connection
     #[clap(long)]
     address: Option<String>,
+
+    /// Authenticate with only this mechanism on --address, instead of trying external, cookie and anonymous in turn.
+    /// Over tcp the bus has no credentials to check, so external does not work there
+    #[clap(long, value_enum, requires = "address")]
+    auth: Option<Auth>,
+
+    /// Connect to the system bus of a local container registered with systemd-machined
+    #[clap(long)]
+    machine: Option<String>,
+
+    /// Connect to the session bus of another user, identified by uid
+    #[clap(long)]
+    user: Option<u32>,
+
+    /// Only list services implementing this interface on any of their objects.
+    /// This introspects every service, so it is a lot slower than the plain listing
+    #[clap(long)]
+    implements: Option<String>,
+
+    /// Leave out borders and spacing, for small terminals
+    #[clap(long)]
+    dense: bool,
+
+    /// Start with this service selected and its objects shown
+    #[clap(long)]
+    service: Option<String>,
+
+    /// Marks the selected item in the panes, instead of >>
+    #[clap(long)]
+    highlight_symbol: Option<String>,
+
+    /// Style of the selected item in the panes, instead of bold
+    #[clap(long, value_enum)]
+    highlight_style: Option<HighlightStyle>,
+
+    /// Move a page at a time while Up or Down is held
+    #[clap(long)]
+    accelerate: bool,
+
+    /// Only introspect the root object of services, not the objects below it
+    #[clap(long)]
+    no_recurse: bool,
+
+    /// Print how long walking each service took to stderr on exit, with the time of every object
+    #[clap(long)]
+    profile: bool,
+
+    /// Values longer than this are cut short in popups, press v to see them in full
+    #[clap(long, default_value_t = 200)]
+    max_value_length: usize,
+
+    /// With --service, walk the objects from this path instead of /
+    #[clap(long, requires = "service")]
+    path: Option<String>,
+
+    /// With --service, open this interface on the object at --path
+    #[clap(long, requires = "service")]
+    interface: Option<String>,
+
+    /// Start on the service that was shown when dtui last quit
+    #[clap(long)]
+    resume: bool,
+
+    /// Answer commands read as lines of JSON from stdin with a line of JSON each on stdout, instead of showing the TUI.
+    /// The commands are described in the README
+    #[clap(long)]
+    script: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the methods of an object as interface.method, one per line, for shell completion
+    Complete {
+        /// Service the object belongs to, like org.freedesktop.DBus
+        service: String,
+        /// Path of the object, like /org/freedesktop/DBus
+        path: String,
+    },
+}
+
+// Finds the system bus socket of a container through the root filesystem of its leader process
+async fn machine_address(machine: &str) -> Result<String, zbus::Error> {
+    let system = Connection::system().await?;
+    let manager = zbus::Proxy::new(
+        &system,
+        "org.freedesktop.machine1",
+        "/org/freedesktop/machine1",
+        "org.freedesktop.machine1.Manager",
+    )
+    .await?;
+    let machine_path: OwnedObjectPath =
+        manager
+            .call("GetMachine", &(machine,))
+            .await
+            .map_err(|err| {
+                zbus::Error::Failure(format!("Could not find machine {}: {}", machine, err))
+            })?;
+    let machine_proxy = zbus::Proxy::new(
+        &system,
+        "org.freedesktop.machine1",
+        machine_path,
+        "org.freedesktop.machine1.Machine",
+    )
+    .await?;
+    let leader: u32 = machine_proxy.get_property("Leader").await?;
+    let socket = format!("/proc/{}/root/run/dbus/system_bus_socket", leader);
+    socket_address(&socket, &format!("the system bus of machine {}", machine))
+}
+
+fn user_address(uid: u32) -> Result<String, zbus::Error> {
+    let socket = format!("/run/user/{}/bus", uid);
+    socket_address(&socket, &format!("the session bus of user {}", uid))
+}
+
+fn socket_address(socket: &str, description: &str) -> Result<String, zbus::Error> {
+    if !Path::new(socket).exists() {
+        return Err(zbus::Error::Failure(format!(
+            "Could not reach {}: {} does not exist or is not accessible",
+            description, socket
+        )));
+    }
+    Ok(format!("unix:path={}", socket))
+}
+
+// The address the bus is found at when none is given, the way zbus looks for it
+fn default_address(bus: BusType) -> String {
+    match bus {
+        BusType::System => std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| "unix:path=/var/run/dbus/system_bus_socket".to_string()),
+        BusType::Session => std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap_or_else(|_| {
+            format!(
+                "unix:path={}/bus",
+                std::env::var("XDG_RUNTIME_DIR").unwrap_or_default()
+            )
+        }),
+    }
+}
+
+// Connects to the bus or peer the arguments point at, returning the connection and its address
+async fn connect(args: &Args) -> Result<(Connection, String), zbus::Error> {
+    let address = if let Some(address) = &args.address {
+        Some(address.clone())
+    } else if let Some(machine) = &args.machine {
+        Some(machine_address(machine).await?)
+    } else if let Some(uid) = args.user {
+        Some(user_address(uid)?)
+    } else {
+        None
+    };
+    let bus = args.bus.unwrap_or(BusType::System);
+    let connection = match (&address, bus) {
+        (Some(address), _) => {
+            let builder = || -> Result<ConnectionBuilder, zbus::Error> {
+                let builder = ConnectionBuilder::address(address.as_str())?;
+                Ok(match args.auth {
+                    Some(auth) => builder.auth_mechanism(auth.into()),
+                    None => builder,
+                })
+            };
+            match builder()?.build().await {
+                Ok(connection) => connection,
+                // Without a message bus on the other end saying Hello fails, so try it as a peer
+                Err(err) if is_not_a_bus(&err) => {
+                    builder()?.p2p().build().await.map_err(|p2p_err| {
+                        zbus::Error::Failure(format!(
+                            "{}\nConnecting to it as a peer failed too: {}",
+                            err, p2p_err
+                        ))
+                    })?
+                }
+                Err(err) => {
+                    return Err(with_hint(
+                        err,
+                        &format!("Nothing is listening on {}", address),
+                    ))
+                }
+            }
+        }
+        (None, BusType::System) => Connection::system().await.map_err(|err| {
+            with_hint(
+                err,
+                "No system bus found, is a dbus daemon running? Try the session bus or --address",
+            )
+        })?,
+        (None, BusType::Session) => Connection::session().await.map_err(|err| {
+            with_hint(
+                err,
+                "No session bus found, are you in a graphical session? Try the system bus or --address",
+            )
+        })?,
+    };
+    Ok((connection, address.unwrap_or_else(|| default_address(bus))))
+}
+
+// Hello is sent to org.freedesktop.DBus, which only a message bus serves, so a peer answers it with one of these
+fn is_not_a_bus(err: &zbus::Error) -> bool {
+    const NOT_SERVED: [&str; 4] = [
+        "org.freedesktop.DBus.Error.UnknownObject",
+        "org.freedesktop.DBus.Error.UnknownInterface",
+        "org.freedesktop.DBus.Error.UnknownMethod",
+        "org.freedesktop.DBus.Error.ServiceUnknown",
+    ];
+    matches!(err, zbus::Error::MethodError(name, _, _) if NOT_SERVED.contains(&name.as_str()))
+}
+
+// Explains the errors people commonly run into when connecting, not_found is used when there is no bus
+fn with_hint(err: zbus::Error, not_found: &str) -> zbus::Error {
+    let hint = match &err {
+        zbus::Error::Address(_) => not_found,
+        zbus::Error::InputOutput(io) => match io.kind() {
+            ErrorKind::NotFound | ErrorKind::ConnectionRefused => not_found,
+            ErrorKind::PermissionDenied => {
+                "Permission denied, you may not be allowed to use this bus"
+            }
+            _ => return err,
+        },
+        zbus::Error::Handshake(_) => "The bus did not accept any of the authentication mechanisms, try another one with --auth",
+        _ => return err,
+    };
+    zbus::Error::Failure(format!("{}\n{}", err, hint))
+}
+
+// Prints interface.method for every method of the object, one per line
+async fn complete(connection: &Connection, service: &str, path: &str) -> Result<(), zbus::Error> {
+    let introspectable_proxy = zbus::fdo::IntrospectableProxy::builder(connection)
+        .destination(service)?
+        .path(path)?
+        .build()
+        .await?;
+    let introspect_xml = introspectable_proxy.introspect().await?;
+    let node = Node::from_reader(introspect_xml.as_bytes())
+        .map_err(|err| zbus::Error::Failure(err.to_string()))?;
+    for interface in node.interfaces() {
+        for method in interface.methods() {
+            println!("{}.{}", interface.name(), method.name());
+        }
+    }
+    Ok(())
 }
 
 // This function is mainly used to make error handling nicer, so that we can cleanup the terminal nicely
-async fn run<B>(terminal: &mut Terminal<B>, args: Args) -> Result<(), zbus::Error>
+async fn run<B>(
+    terminal: &mut Terminal<B>,
+    args: Args,
+    keymap: KeyMap,
+    profile: Option<Profile>,
+) -> Result<(), zbus::Error>
 where
     B: Backend,
 {
     // create app and run it
     let tick_rate = Duration::from_millis(250);
-    let mut connection = match args.bus {
-        BusType::System => Connection::system().await?,
-        BusType::Session => Connection::session().await?,
+    let target = connection_target(&args);
+    // Keep the spinner going until connecting is done, it can take a while on remote buses
+    let (connection, address) = {
+        let connecting = connect(&args);
+        tokio::pin!(connecting);
+        let mut tick = 0;
+        loop {
+            terminal.draw(|frame| ui::connecting(frame, &target, tick))?;
+            tokio::select! {
+                connected = &mut connecting => break connected?,
+                _ = tokio::time::sleep(Duration::from_millis(100)) => tick += 1,
+            }
+            // Raw mode is already on, so giving up has to be done with keys
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()? {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl_c || keymap.action(&key) == Some(Action::Quit) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+    let peer = if connection.is_bus() {
+        None
+    } else {
+        Some(address.clone())
+    };
+    let service = args
+        .service
+        .or_else(|| args.resume.then(load_last_service).flatten())
+        .map(OwnedBusName::try_from)
+        .transpose()
+        .map_err(zbus::Error::from)?;
+    let path = args
+        .path
+        .map(OwnedObjectPath::try_from)
+        .transpose()
+        .map_err(zbus::Error::from)?;
+    let focus = Focus {
+        service,
+        path,
+        interface: args.interface,
     };
-    if let Some(address) = args.address {
-        connection = ConnectionBuilder::address(address.as_str())?
-            .build()
-            .await?;
-    }
     let (dbus_handler_sender, app_receiver) = mpsc::channel::<AppMessage>(16);
-    let dbus_handler = DbusActorHandle::new(dbus_handler_sender, connection);
+    let dbus_handler =
+        DbusActorHandle::new(dbus_handler_sender, connection, !args.no_recurse, profile);
     // setup terminal
-    let app = App::new(app_receiver, dbus_handler);
-    run_app(terminal, app, tick_rate).await
+    let mut app = App::new(
+        app_receiver,
+        dbus_handler,
+        args.implements,
+        peer,
+        args.dense,
+        focus,
+        address,
+    );
+    app.keymap = keymap;
+    app.favorites = load_favorites();
+    app.max_value_length = args.max_value_length;
+    app.accelerate = args.accelerate;
+    if let Some(symbol) = args.highlight_symbol {
+        app.theme.highlight_symbol = symbol;
+    }
+    if let Some(style) = args.highlight_style {
+        app.theme.highlight_style = style.into();
+    }
+    app.theme.use_color = use_color();
+    let res = run_app(terminal, &mut app, tick_rate).await;
+    if let Some(service) = app.objects_service() {
+        save_last_service(service);
+    }
+    save_favorites(&app.favorites);
+    res
+}
+
+// Slowest objects first, every introspection of an object is a round trip. Objects that failed are listed too
+fn print_profile(walks: &[WalkProfile]) {
+    for walk in walks {
+        match &walk.error {
+            Some(error) => eprintln!("{}: failed in {:.1?}, {}", walk.service, walk.total, error),
+            None => eprintln!(
+                "{}: {} objects in {:.1?}",
+                walk.service,
+                walk.objects.len(),
+                walk.total
+            ),
+        }
+        for (path, time) in walk.objects.iter().sorted_by(|a, b| b.1.cmp(&a.1)) {
+            eprintln!("  {:>10.1?} {}", time, path);
+        }
+    }
+}
+
+// NO_COLOR turns colors off when set to anything, as does a dumb terminal. See https://no-color.org
+fn use_color() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !no_color && !dumb
+}
+
+// What is being connected to, in words
+fn connection_target(args: &Args) -> String {
+    if let Some(address) = &args.address {
+        address.clone()
+    } else if let Some(machine) = &args.machine {
+        format!("the system bus of {}", machine)
+    } else if let Some(uid) = args.user {
+        format!("the session bus of user {}", uid)
+    } else {
+        match args.bus.unwrap_or(BusType::System) {
+            BusType::System => "the system bus".to_string(),
+            BusType::Session => "the session bus".to_string(),
+        }
+    }
+}
+
+// Actions bound to other keys, in $XDG_CONFIG_HOME/dtui/keys. Without the file the default keys are used
+fn load_keymap() -> Result<KeyMap, String> {
+    let Some(config_home) = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    else {
+        return Ok(KeyMap::default());
+    };
+    let file = config_home.join("dtui").join("keys");
+    match std::fs::read_to_string(&file) {
+        Ok(contents) => {
+            KeyMap::parse(&contents).map_err(|err| format!("{}: {}", file.display(), err))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(KeyMap::default()),
+        Err(err) => Err(format!("{}: {}", file.display(), err)),
+    }
+}
+
+// Where state such as the last shown service for --resume is kept between runs
+fn state_file(name: &str) -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(state_home.join("dtui").join(name))
+}
+
+// A missing or unreadable state file just means there is nothing to resume
+fn load_last_service() -> Option<String> {
+    let service = std::fs::read_to_string(state_file("last_service")?).ok()?;
+    Some(service.trim().to_string()).filter(|service| !service.is_empty())
+}
+
+// Failing to save is not worth bothering anyone about when quitting
+fn save_last_service(service: &str) {
+    save_state("last_service", service);
+}
+
+// Favorite services, one per line
+fn load_favorites() -> BTreeSet<String> {
+    state_file("favorites")
+        .and_then(|file| std::fs::read_to_string(file).ok())
+        .map(|favorites| {
+            favorites
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn save_favorites(favorites: &BTreeSet<String>) {
+    let mut contents = favorites.iter().join("\n");
+    contents.push('\n');
+    save_state("favorites", &contents);
+}
+
+fn save_state(name: &str, contents: &str) {
+    if let Some(file) = state_file(name) {
+        if let Some(dir) = file.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = std::fs::write(file, contents);
+    }
 }
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    // Only read when it decides the bus, so it doesn't warn when a bus or address is given
+    if args.bus.is_none() && args.address.is_none() && args.machine.is_none() && args.user.is_none()
+    {
+        args.bus = Some(default_bus());
+    }
+    if let Some(Command::Complete { service, path }) = &args.command {
+        let res = match connect(&args).await {
+            Ok((connection, _)) => complete(&connection, service, path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.script {
+        let connection = match connect(&args).await {
+            Ok((connection, _)) => connection,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        let (dbus_handler_sender, app_receiver) = mpsc::channel::<AppMessage>(16);
+        let dbus_handler =
+            DbusActorHandle::new(dbus_handler_sender, connection, !args.no_recurse, None);
+        script::run(dbus_handler, app_receiver).await?;
+        return Ok(());
+    }
+
+    // A broken keys file is reported before the terminal is taken over
+    let keymap = match load_keymap() {
+        Ok(keymap) => keymap,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -76,7 +594,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let res = run(&mut terminal, args).await;
+    let profile = args.profile.then(Profile::default);
+    let res = run(&mut terminal, args, keymap, profile.clone()).await;
 
     // restore terminal
     disable_raw_mode()?;
@@ -90,5 +609,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     if let Err(err) = res {
         println!("{}", err);
     }
+    if let Some(profile) = profile {
+        print_profile(&profile.lock().unwrap());
+    }
     Ok(())
 }
@@ -0,0 +1,120 @@
+use std::fmt::Write;
+
+use dtui::stateful_tree::{args_with_direction, method_label, signal_label, Nodes};
+use itertools::Itertools;
+use zbus_xml::{ArgDirection, Interface};
+
+/// Document the objects of a service, with a heading per object and interface
+pub fn to_markdown(service: &str, nodes: &Nodes) -> String {
+    let mut markdown = format!("# {}\n", service);
+    for (object_name, node) in nodes.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+        let _ = write!(markdown, "\n## {}\n", object_name);
+        match node {
+            Ok(node) => {
+                for interface in node.interfaces() {
+                    write_interface(&mut markdown, interface);
+                }
+            }
+            Err(err) => {
+                let _ = writeln!(markdown, "\n{}", err);
+            }
+        }
+    }
+    markdown
+}
+
+fn write_interface(markdown: &mut String, interface: &Interface) {
+    let _ = write!(markdown, "\n### {}\n", interface.name());
+    let methods: Vec<String> = interface
+        .methods()
+        .iter()
+        .map(|method| {
+            let inputs = args_with_direction(method.args(), ArgDirection::In, false);
+            let outputs = args_with_direction(method.args(), ArgDirection::Out, false);
+            method_label(method.name().as_str(), &inputs, &outputs)
+        })
+        .collect();
+    let properties: Vec<String> = interface
+        .properties()
+        .iter()
+        .map(|property| format!("{}: {}", property.name(), property.ty()))
+        .collect();
+    let signals: Vec<String> = interface
+        .signals()
+        .iter()
+        .map(|signal| signal_label(signal.name().as_str(), signal.args(), false))
+        .collect();
+    for (heading, members) in [
+        ("Methods", methods),
+        ("Properties", properties),
+        ("Signals", signals),
+    ] {
+        if members.is_empty() {
+            continue;
+        }
+        let _ = write!(markdown, "\n#### {}\n\n", heading);
+        for member in members {
+            let _ = writeln!(markdown, "- `{}`", member);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus_xml::Node;
+
+    use super::*;
+
+    const GREETER_XML: &str = r#"
+<node>
+  <interface name="org.example.Greeter">
+    <method name="SayHello">
+      <arg name="name" type="s" direction="in"/>
+      <arg name="greeting" type="s" direction="out"/>
+    </method>
+    <property name="Count" type="u" access="read"/>
+    <signal name="Greeted">
+      <arg name="name" type="s"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+    #[test]
+    fn objects_are_documented_in_path_order() {
+        let mut nodes = Nodes::new();
+        nodes.insert(
+            "/org/example/Greeter".to_string(),
+            Ok(Node::try_from(GREETER_XML).unwrap()),
+        );
+        nodes.insert(
+            "/broken".to_string(),
+            Err("Invalid introspection data: unexpected end".to_string()),
+        );
+        assert_eq!(
+            to_markdown("org.example.Greeter", &nodes),
+            "# org.example.Greeter
+
+## /broken
+
+Invalid introspection data: unexpected end
+
+## /org/example/Greeter
+
+### org.example.Greeter
+
+#### Methods
+
+- `SayHello(name: s) => greeting: s`
+
+#### Properties
+
+- `Count: u`
+
+#### Signals
+
+- `Greeted(name: s)`
+"
+        );
+    }
+}
@@ -1,13 +1,68 @@
 use std::collections::HashMap;
 
-use zbus::names::OwnedBusName;
-use zbus_xml::Node;
+use dtui::stateful_tree::Nodes;
+use zbus::{
+    names::{OwnedBusName, OwnedInterfaceName, OwnedUniqueName},
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
 
 pub enum DbusMessage {
-    GetObjects(OwnedBusName),
-    ServiceRequest(),
+    // Walk the objects of a service, starting at the given path. The service is None for a peer
+    GetObjects(Option<OwnedBusName>, OwnedObjectPath),
+    // Optionally only list services implementing the given interface
+    ServiceRequest(Option<String>),
+    // The service is None when connected directly to a peer
+    GetAllProperties(Option<OwnedBusName>, OwnedObjectPath, OwnedInterfaceName),
+    GetPids(Vec<OwnedBusName>),
+    // The unique names owning the given well-known names
+    GetOwners(Vec<OwnedBusName>),
+    // GetAll and the given getter methods of an interface, the methods take no arguments
+    GetSnapshot {
+        service: Option<OwnedBusName>,
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        getters: Vec<String>,
+    },
+    // Call a method with the given arguments, the reply is sent back as is
+    CallMethod {
+        service: Option<OwnedBusName>,
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        method: String,
+        args: Vec<OwnedValue>,
+    },
 }
 pub enum AppMessage {
-    Objects(HashMap<String, Node<'static>>),
+    // Objects of a service, sent in batches while it is walked. The service is None for a peer
+    Objects {
+        service: Option<OwnedBusName>,
+        nodes: Nodes,
+        complete: bool,
+    },
+    // Walking a service stopped, the objects sent until then are all there is
+    ObjectsFailed {
+        service: Option<OwnedBusName>,
+        error: String,
+    },
     Services(Vec<OwnedBusName>),
+    // Listing the names on the bus failed, so there are no services to show
+    ServicesFailed(String),
+    Properties {
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        properties: Result<HashMap<String, OwnedValue>, zbus::Error>,
+    },
+    // Properties and the results of the getter methods of an interface, with the replies formatted
+    Snapshot {
+        path: OwnedObjectPath,
+        interface: OwnedInterfaceName,
+        properties: Result<HashMap<String, OwnedValue>, zbus::Error>,
+        getters: Vec<(String, Result<String, zbus::Error>)>,
+    },
+    // Process ids of the owners of the names, names without one are left out
+    Pids(HashMap<OwnedBusName, u32>),
+    // Unique names of the owners of the names, names without one are left out
+    Owners(HashMap<OwnedBusName, OwnedUniqueName>),
+    // The values a called method replied with
+    Reply(Result<Vec<OwnedValue>, zbus::Error>),
 }
@@ -0,0 +1,634 @@
+use std::{collections::HashMap, fmt::Display};
+
+use dtui::{signature::describe_signature, stateful_tree::Nodes};
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use tokio::{
+    io::{self, AsyncBufReadExt, BufReader},
+    sync::mpsc::Receiver,
+};
+use zbus::{
+    names::{MemberName, OwnedBusName, OwnedInterfaceName},
+    zvariant::{Array, Dict, OwnedObjectPath, OwnedValue, Signature, Str, StructureBuilder, Value},
+};
+use zbus_xml::{Arg, ArgDirection, Interface, Node, PropertyAccess};
+
+use crate::{dbus_handler::DbusActorHandle, messages::AppMessage};
+
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+
+// A command read from stdin, the service is left out when connected to a peer
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Request {
+    Introspect {
+        service: Option<String>,
+        path: String,
+    },
+    GetProperty {
+        service: Option<String>,
+        path: String,
+        interface: String,
+        property: String,
+    },
+    Call {
+        service: Option<String>,
+        path: String,
+        interface: String,
+        method: String,
+        #[serde(default)]
+        args: Vec<Json>,
+    },
+}
+
+// Why a command failed. The code tells the kinds of failure apart, name is the D-Bus error if there is one
+struct Failure {
+    code: &'static str,
+    name: Option<String>,
+    message: String,
+}
+
+impl Failure {
+    fn invalid(err: impl Display) -> Self {
+        Self {
+            code: "invalid-request",
+            name: None,
+            message: err.to_string(),
+        }
+    }
+
+    fn dbus(err: zbus::Error) -> Self {
+        match err {
+            zbus::Error::MethodError(name, detail, _) => Self {
+                code: "dbus",
+                name: Some(name.to_string()),
+                message: detail.unwrap_or_default(),
+            },
+            err => Self {
+                code: "dbus",
+                name: None,
+                message: err.to_string(),
+            },
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(name) = &self.name {
+            error["name"] = json!(name);
+        }
+        error
+    }
+}
+
+/// Answers commands read from stdin, one JSON object per line, with a line of JSON each on stdout until stdin is closed.
+/// Commands are answered one at a time, in the order they come in
+pub async fn run(handle: DbusActorHandle, mut receiver: Receiver<AppMessage>) -> io::Result<()> {
+    let mut lines = BufReader::new(io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, answer) = match serde_json::from_str::<Json>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned().unwrap_or(Json::Null);
+                (id, answer(request, &handle, &mut receiver).await)
+            }
+            Err(err) => (Json::Null, Err(Failure::invalid(err))),
+        };
+        let reply = match answer {
+            Ok(result) => json!({ "id": id, "result": result }),
+            Err(failure) => json!({ "id": id, "error": failure.to_json() }),
+        };
+        println!("{}", reply);
+    }
+    Ok(())
+}
+
+async fn answer(
+    request: Json,
+    handle: &DbusActorHandle,
+    receiver: &mut Receiver<AppMessage>,
+) -> Result<Json, Failure> {
+    match Request::deserialize(request).map_err(Failure::invalid)? {
+        Request::Introspect { service, path } => {
+            handle
+                .request_objects_from(bus_name(service)?, object_path(path)?)
+                .await;
+            let mut nodes = Nodes::new();
+            let mut error = None;
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    AppMessage::Objects {
+                        nodes: batch,
+                        complete,
+                        ..
+                    } => {
+                        nodes.extend(batch);
+                        if complete {
+                            break;
+                        }
+                    }
+                    AppMessage::ObjectsFailed { error: failed, .. } => error = Some(failed),
+                    _ => {}
+                }
+            }
+            match error {
+                Some(message) => Err(Failure {
+                    code: "dbus",
+                    name: None,
+                    message,
+                }),
+                None => Ok(objects_to_json(&nodes)),
+            }
+        }
+        Request::GetProperty {
+            service,
+            path,
+            interface,
+            property,
+        } => {
+            let interface_name =
+                OwnedInterfaceName::try_from(interface.as_str()).map_err(Failure::invalid)?;
+            handle
+                .request_all_properties(bus_name(service)?, object_path(path)?, interface_name)
+                .await;
+            let properties = loop {
+                match receiver.recv().await {
+                    Some(AppMessage::Properties { properties, .. }) => break properties,
+                    Some(_) => {}
+                    None => return Err(Failure::dbus(zbus::Error::InvalidReply)),
+                }
+            };
+            let mut properties = properties.map_err(Failure::dbus)?;
+            properties
+                .remove(&property)
+                .map(|value| to_json(&value))
+                .ok_or_else(|| Failure {
+                    code: "unknown-property",
+                    name: None,
+                    message: format!("{} has no property {}", interface, property),
+                })
+        }
+        Request::Call {
+            service,
+            path,
+            interface,
+            method,
+            args,
+        } => {
+            let service = bus_name(service)?;
+            let path = object_path(path)?;
+            let interface =
+                OwnedInterfaceName::try_from(interface.as_str()).map_err(Failure::invalid)?;
+            MemberName::try_from(method.as_str()).map_err(Failure::invalid)?;
+            // Methods without arguments are called right away, so services without introspection work too
+            let args = if args.is_empty() {
+                Vec::new()
+            } else {
+                let introspectable = OwnedInterfaceName::try_from(INTROSPECTABLE).unwrap();
+                let reply = call(
+                    handle,
+                    receiver,
+                    &service,
+                    &path,
+                    introspectable,
+                    "Introspect",
+                    Vec::new(),
+                )
+                .await?;
+                let xml = match reply.first().map(|xml| &**xml) {
+                    Some(Value::Str(xml)) => xml.as_str(),
+                    _ => return Err(Failure::dbus(zbus::Error::InvalidReply)),
+                };
+                let node = Node::from_reader(xml.as_bytes()).map_err(|err| Failure {
+                    code: "dbus",
+                    name: None,
+                    message: format!("Invalid introspection data: {}", err),
+                })?;
+                let signatures = in_signatures(&node, &interface, &method).ok_or_else(|| {
+                    Failure::invalid(format!(
+                        "{} has no method {} on {}",
+                        interface, method, path
+                    ))
+                })?;
+                args_from_json(&args, &signatures).map_err(Failure::invalid)?
+            };
+            let values = call(handle, receiver, &service, &path, interface, &method, args).await?;
+            Ok(Json::Array(
+                values.iter().map(|value| to_json(value)).collect(),
+            ))
+        }
+    }
+}
+
+// Calls a method through the actor and waits for its reply
+async fn call(
+    handle: &DbusActorHandle,
+    receiver: &mut Receiver<AppMessage>,
+    service: &Option<OwnedBusName>,
+    path: &OwnedObjectPath,
+    interface: OwnedInterfaceName,
+    method: &str,
+    args: Vec<OwnedValue>,
+) -> Result<Vec<OwnedValue>, Failure> {
+    handle
+        .request_call(
+            service.clone(),
+            path.clone(),
+            interface,
+            method.to_string(),
+            args,
+        )
+        .await;
+    loop {
+        match receiver.recv().await {
+            Some(AppMessage::Reply(reply)) => return reply.map_err(Failure::dbus),
+            Some(_) => {}
+            None => return Err(Failure::dbus(zbus::Error::InvalidReply)),
+        }
+    }
+}
+
+// Signatures of the arguments the method takes, None if the object has no such method
+fn in_signatures(node: &Node, interface: &str, method: &str) -> Option<Vec<String>> {
+    let interface = node
+        .interfaces()
+        .iter()
+        .find(|candidate| candidate.name() == interface)?;
+    let method = interface
+        .methods()
+        .iter()
+        .find(|candidate| candidate.name() == method)?;
+    // Arguments of methods are in unless they say otherwise
+    let signatures = method
+        .args()
+        .iter()
+        .filter(|arg| arg.direction() != Some(ArgDirection::Out))
+        .map(|arg| arg.ty().to_string())
+        .collect();
+    Some(signatures)
+}
+
+// The arguments of a call, converted to the types the method takes
+fn args_from_json(args: &[Json], signatures: &[String]) -> Result<Vec<OwnedValue>, String> {
+    if args.len() != signatures.len() {
+        return Err(format!(
+            "the method takes {} arguments of types ({}), {} were given",
+            signatures.len(),
+            signatures.join(", "),
+            args.len()
+        ));
+    }
+    args.iter()
+        .zip(signatures)
+        .enumerate()
+        .map(|(position, (arg, signature))| {
+            let value = from_json(arg, signature)
+                .map_err(|err| format!("argument {}: {}", position + 1, err))?;
+            OwnedValue::try_from(value).map_err(|err| err.to_string())
+        })
+        .collect()
+}
+
+// The value of a single complete type from JSON, the opposite of to_json.
+// Variants are given as {"type": signature, "value": value}, as JSON doesn't tell which type to send
+fn from_json(json: &Json, signature: &str) -> Result<Value<'static>, String> {
+    let mismatch = || {
+        let signature = Signature::from_str_unchecked(signature);
+        format!("{} is not a {}", json, describe_signature(&signature))
+    };
+    let value = match signature.as_bytes() {
+        b"y" => integer::<u8>(json).map(Value::from),
+        b"b" => json.as_bool().map(Value::from),
+        b"n" => integer::<i16>(json).map(Value::from),
+        b"q" => integer::<u16>(json).map(Value::from),
+        b"i" => integer::<i32>(json).map(Value::from),
+        b"u" => integer::<u32>(json).map(Value::from),
+        b"x" => integer::<i64>(json).map(Value::from),
+        b"t" => integer::<u64>(json).map(Value::from),
+        b"d" => json.as_f64().map(Value::from),
+        b"s" => json.as_str().map(|text| Value::from(text.to_string())),
+        b"o" => json
+            .as_str()
+            .and_then(|path| OwnedObjectPath::try_from(path).ok())
+            .map(|path| Value::from(path.into_inner())),
+        b"g" => json
+            .as_str()
+            .and_then(|text| Signature::try_from(text.to_string()).ok())
+            .map(Value::from),
+        b"v" => {
+            let (Some(Json::String(inner)), Some(value)) = (json.get("type"), json.get("value"))
+            else {
+                let expected = r#"{"type": signature, "value": value}"#;
+                return Err(format!("{} is not a variant like {}", json, expected));
+            };
+            match complete_types(inner)?[..] {
+                [inner] => Some(Value::Value(Box::new(from_json(value, inner)?))),
+                _ => return Err(format!("{} is not a single complete type", inner)),
+            }
+        }
+        [b'a', b'{', ..] => {
+            let Some(object) = json.as_object() else {
+                return Err(mismatch());
+            };
+            let [key_signature, value_signature] =
+                complete_types(&signature[2..signature.len() - 1])?[..]
+            else {
+                return Err(format!("{} is not a valid dict type", signature));
+            };
+            let mut dict = Dict::new(
+                owned_signature(key_signature)?,
+                owned_signature(value_signature)?,
+            );
+            for (key, value) in object {
+                // Keys are strings in JSON, so other basic types are parsed from them
+                let key = match key_signature {
+                    "s" | "o" | "g" => Json::String(key.clone()),
+                    _ => serde_json::from_str(key)
+                        .map_err(|_| format!("{} is not a valid key", key))?,
+                };
+                let key = from_json(&key, key_signature)?;
+                let value = from_json(value, value_signature)?;
+                dict.append(key, value).map_err(|err| err.to_string())?;
+            }
+            Some(Value::Dict(dict))
+        }
+        [b'a', ..] => {
+            let Some(elements) = json.as_array() else {
+                return Err(mismatch());
+            };
+            let element_signature = &signature[1..];
+            let mut array = Array::new(owned_signature(element_signature)?);
+            for element in elements {
+                let element = from_json(element, element_signature)?;
+                array.append(element).map_err(|err| err.to_string())?;
+            }
+            Some(Value::Array(array))
+        }
+        [b'(', ..] => {
+            let Some(fields) = json.as_array() else {
+                return Err(mismatch());
+            };
+            let signatures = complete_types(&signature[1..signature.len() - 1])?;
+            if fields.len() != signatures.len() {
+                return Err(mismatch());
+            }
+            let structure = fields.iter().zip(signatures).try_fold(
+                StructureBuilder::new(),
+                |structure, (field, signature)| {
+                    Ok::<_, String>(structure.append_field(from_json(field, signature)?))
+                },
+            )?;
+            Some(Value::Structure(structure.build()))
+        }
+        _ => {
+            return Err(format!(
+                "arguments of type {} can't be given as JSON",
+                signature
+            ))
+        }
+    };
+    value.ok_or_else(mismatch)
+}
+
+fn owned_signature(signature: &str) -> Result<Signature<'static>, String> {
+    Signature::try_from(signature.to_string()).map_err(|err| err.to_string())
+}
+
+// A JSON number as an integer type, None if it isn't a whole number in its range
+fn integer<T: TryFrom<i128>>(json: &Json) -> Option<T> {
+    let number = json
+        .as_i64()
+        .map(i128::from)
+        .or_else(|| json.as_u64().map(i128::from))?;
+    T::try_from(number).ok()
+}
+
+// Splits a signature into its complete types, like "sa{sv}(ii)" into "s", "a{sv}" and "(ii)"
+fn complete_types(signature: &str) -> Result<Vec<&str>, String> {
+    // Length of the complete type at the start of the signature
+    fn type_length(signature: &[u8]) -> Option<usize> {
+        match signature.first()? {
+            b'a' => Some(1 + type_length(&signature[1..])?),
+            open @ (b'(' | b'{') => {
+                let close = if *open == b'(' { b')' } else { b'}' };
+                let mut length = 1;
+                while *signature.get(length)? != close {
+                    length += type_length(&signature[length..])?;
+                }
+                Some(length + 1)
+            }
+            _ => Some(1),
+        }
+    }
+    let mut types = Vec::new();
+    let mut rest = signature;
+    while !rest.is_empty() {
+        let length = type_length(rest.as_bytes())
+            .ok_or_else(|| format!("{} is not a valid signature", signature))?;
+        let (complete, remaining) = rest.split_at(length);
+        types.push(complete);
+        rest = remaining;
+    }
+    Ok(types)
+}
+
+fn bus_name(service: Option<String>) -> Result<Option<OwnedBusName>, Failure> {
+    service
+        .map(OwnedBusName::try_from)
+        .transpose()
+        .map_err(Failure::invalid)
+}
+
+fn object_path(path: String) -> Result<OwnedObjectPath, Failure> {
+    OwnedObjectPath::try_from(path).map_err(Failure::invalid)
+}
+
+// The objects in path order, with their interfaces or why they could not be introspected
+fn objects_to_json(nodes: &Nodes) -> Json {
+    let objects = nodes
+        .iter()
+        .sorted_by(|a, b| a.0.cmp(b.0))
+        .map(|(path, node)| match node {
+            Ok(node) => json!({
+                "path": path,
+                "interfaces": node.interfaces().iter().map(interface_to_json).collect_vec(),
+            }),
+            Err(err) => json!({ "path": path, "error": err }),
+        })
+        .collect_vec();
+    json!({ "objects": objects })
+}
+
+fn interface_to_json(interface: &Interface) -> Json {
+    let args = |args: &[Arg]| {
+        args.iter()
+            .map(|arg| json!({ "name": arg.name(), "type": arg.ty().to_string() }))
+            .collect_vec()
+    };
+    let methods = interface.methods().iter().map(|method| {
+        // Arguments of methods are in unless they say otherwise
+        let (outputs, inputs): (Vec<_>, Vec<_>) = method
+            .args()
+            .iter()
+            .cloned()
+            .partition(|arg| arg.direction() == Some(ArgDirection::Out));
+        json!({ "name": method.name().as_str(), "in": args(&inputs), "out": args(&outputs) })
+    });
+    let signals = interface
+        .signals()
+        .iter()
+        .map(|signal| json!({ "name": signal.name().as_str(), "args": args(signal.args()) }));
+    let properties = interface.properties().iter().map(|property| {
+        let access = match property.access() {
+            PropertyAccess::Read => "read",
+            PropertyAccess::Write => "write",
+            PropertyAccess::ReadWrite => "readwrite",
+        };
+        json!({ "name": property.name().as_str(), "type": property.ty().to_string(), "access": access })
+    });
+    json!({
+        "name": interface.name().as_str(),
+        "methods": methods.collect_vec(),
+        "signals": signals.collect_vec(),
+        "properties": properties.collect_vec(),
+    })
+}
+
+// Basic values map onto JSON directly, variants are unwrapped and structures become arrays
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::U8(value) => json!(value),
+        Value::Bool(value) => json!(value),
+        Value::I16(value) => json!(value),
+        Value::U16(value) => json!(value),
+        Value::I32(value) => json!(value),
+        Value::U32(value) => json!(value),
+        Value::I64(value) => json!(value),
+        Value::U64(value) => json!(value),
+        Value::F64(value) => json!(value),
+        Value::Str(value) => json!(value.as_str()),
+        Value::Signature(value) => json!(value.as_str()),
+        Value::ObjectPath(value) => json!(value.as_str()),
+        Value::Value(value) => to_json(value),
+        Value::Array(array) => Json::Array(array.inner().iter().map(to_json).collect()),
+        Value::Structure(structure) => {
+            Json::Array(structure.fields().iter().map(to_json).collect())
+        }
+        Value::Dict(dict) => dict_to_json(dict),
+        value => json!(value.to_string()),
+    }
+}
+
+// JSON objects only have string keys, so dicts with keys other than strings and paths are given as text
+fn dict_to_json(dict: &Dict) -> Json {
+    let key = dict.full_signature().as_bytes().get(2).copied();
+    let entries: Option<Vec<(String, OwnedValue)>> =
+        dict.try_clone().ok().and_then(|dict| match key {
+            Some(b's') => HashMap::<Str, OwnedValue>::try_from(dict).ok().map(|map| {
+                map.into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect()
+            }),
+            Some(b'o') => HashMap::<OwnedObjectPath, OwnedValue>::try_from(dict)
+                .ok()
+                .map(|map| {
+                    map.into_iter()
+                        .map(|(key, value)| (key.to_string(), value))
+                        .collect()
+                }),
+            _ => None,
+        });
+    match entries {
+        Some(entries) => Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, to_json(&value)))
+                .collect(),
+        ),
+        None => json!(dict.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn values_map_onto_json() {
+        assert_eq!(to_json(&Value::from(42u32)), json!(42));
+        assert_eq!(to_json(&Value::from("text")), json!("text"));
+        assert_eq!(to_json(&Value::from(vec![0xdeu8, 1])), json!([222, 1]));
+        assert_eq!(
+            to_json(&Value::from((true, Value::from(1.5f64)))),
+            json!([true, 1.5])
+        );
+    }
+
+    #[test]
+    fn dicts_with_string_keys_become_objects() {
+        let dict = HashMap::from([("a", Value::from(1i32)), ("b", Value::from("x"))]);
+        assert_eq!(to_json(&Value::from(dict)), json!({ "a": 1, "b": "x" }));
+    }
+
+    #[test]
+    fn signatures_are_split_into_complete_types() {
+        assert_eq!(
+            complete_types("sa{sv}(ia(yy))as").unwrap(),
+            ["s", "a{sv}", "(ia(yy))", "as"]
+        );
+        assert!(complete_types("a{sv").is_err());
+    }
+
+    #[test]
+    fn arguments_are_converted_to_their_types() {
+        let cases = [
+            (json!(7), "u"),
+            (json!(-1), "x"),
+            (json!("/org/example"), "o"),
+            (json!([1, 2]), "ay"),
+            (json!(["one", true]), "(sb)"),
+            (json!({ "a": 1, "b": 2 }), "a{si}"),
+            (json!({ "7": "seven" }), "a{us}"),
+        ];
+        for (json, signature) in cases {
+            let value = from_json(&json, signature).unwrap();
+            assert_eq!(value.value_signature(), signature);
+            let expected = match signature {
+                "a{us}" => json!("{uint32 7: \"seven\"}"),
+                _ => json,
+            };
+            assert_eq!(to_json(&value), expected);
+        }
+    }
+
+    #[test]
+    fn variants_say_their_type() {
+        let variant = json!({ "type": "q", "value": 3 });
+        let value = from_json(&variant, "v").unwrap();
+        assert_eq!(value, Value::Value(Box::new(Value::U16(3))));
+        assert!(from_json(&json!(3), "v").is_err());
+    }
+
+    #[test]
+    fn arguments_of_the_wrong_type_are_refused() {
+        assert_eq!(
+            from_json(&json!(-1), "u").unwrap_err(),
+            "-1 is not a uint32"
+        );
+        assert_eq!(from_json(&json!(1), "s").unwrap_err(), "1 is not a string");
+        assert!(from_json(&json!([1]), "(ii)").is_err());
+        let signatures = ["s".to_string()];
+        assert!(args_from_json(&[], &signatures).is_err());
+    }
+
+    #[test]
+    fn dicts_with_other_keys_are_kept_as_text() {
+        let dict = HashMap::from([(7u32, "seven")]);
+        assert_eq!(to_json(&Value::from(dict)), json!("{uint32 7: \"seven\"}"));
+    }
+}
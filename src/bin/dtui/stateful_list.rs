@@ -13,7 +13,28 @@ impl<T> StatefulList<T> {
         }
     }
 
+    /// Replace the items, keeping the selected item selected if it is still there.
+    /// If it is gone, the item that took its place is selected instead
+    pub fn replace_items(&mut self, items: Vec<T>)
+    where
+        T: PartialEq,
+    {
+        let selected = self.state.selected().map(|i| {
+            self.items
+                .get(i)
+                .and_then(|item| items.iter().position(|new| new == item))
+                .unwrap_or(i)
+                .min(items.len().saturating_sub(1))
+        });
+        self.items = items;
+        self.state
+            .select(selected.filter(|_| !self.items.is_empty()));
+    }
+
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -28,6 +49,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -41,7 +65,70 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
+    /// Move the selection down by step items, stopping at the last instead of wrapping around
+    pub fn down_by(&mut self, step: usize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = self
+            .state
+            .selected()
+            .map_or(0, |i| i.saturating_add(step).min(self.items.len() - 1));
+        self.state.select(Some(i));
+    }
+
+    /// Move the selection up by step items, stopping at the first instead of wrapping around
+    pub fn up_by(&mut self, step: usize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = self.state.selected().map_or(0, |i| i.saturating_sub(step));
+        self.state.select(Some(i));
+    }
+
     pub fn unselect(&mut self) {
         self.state.select(None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_with_selected(items: &[&'static str], selected: usize) -> StatefulList<&'static str> {
+        let mut list = StatefulList::with_items(items.to_vec());
+        list.state.select(Some(selected));
+        list
+    }
+
+    #[test]
+    fn selected_item_stays_selected_when_it_moves() {
+        let mut list = list_with_selected(&["a", "b", "c"], 1);
+        list.replace_items(vec!["new", "a", "c", "b"]);
+        assert_eq!(list.state.selected(), Some(3));
+    }
+
+    #[test]
+    fn item_that_took_the_place_of_a_vanished_one_is_selected() {
+        let mut list = list_with_selected(&["a", "b", "c"], 1);
+        list.replace_items(vec!["a", "c"]);
+        assert_eq!(list.state.selected(), Some(1));
+        // The last item vanished, so the one before it is selected
+        list.replace_items(vec!["a"]);
+        assert_eq!(list.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn selection_is_cleared_when_the_list_is_empty() {
+        let mut list = list_with_selected(&["a", "b"], 0);
+        list.replace_items(Vec::new());
+        assert_eq!(list.state.selected(), None);
+    }
+
+    #[test]
+    fn nothing_is_selected_if_nothing_was() {
+        let mut list = StatefulList::with_items(vec!["a"]);
+        list.replace_items(vec!["a", "b"]);
+        assert_eq!(list.state.selected(), None);
+    }
+}
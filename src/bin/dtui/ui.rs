@@ -1,14 +1,42 @@
 use ratatui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::Span,
-    widgets::{Block, BorderType, Borders, List, ListItem, Paragraph},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
+use std::borrow::Cow;
+
+use dtui::truncate::truncate_to_width;
 use tui_tree_widget::Tree;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    app::{App, PropertiesView, ServiceSort, WorkingArea},
+    keymap::Action,
+};
+
+/// How the selected item is marked in the panes
+pub struct Theme {
+    pub highlight_symbol: String,
+    pub highlight_style: Style,
+    /// Without colors the focused pane is shown in bold and reverse video instead
+    pub use_color: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            highlight_symbol: ">> ".to_string(),
+            highlight_style: Style::default().add_modifier(Modifier::BOLD),
+            use_color: true,
+        }
+    }
+}
 
-use crate::app::{App, WorkingArea};
+// Below this width only the focused pane is shown, using the full width
+const NARROW_WIDTH: u16 = 60;
 
 fn working_area_border(app: &App, working_area: WorkingArea) -> Color {
     if app.working_area == working_area {
@@ -18,55 +46,386 @@ fn working_area_border(app: &App, working_area: WorkingArea) -> Color {
     }
 }
 
+// The block around a pane, in dense mode only the title is kept and it shows the focus
+fn pane_block<'a>(app: &App, working_area: WorkingArea, title: String) -> Block<'a> {
+    let focus = if app.theme.use_color {
+        Style::default().fg(working_area_border(app, working_area))
+    } else if app.working_area == working_area {
+        Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+    } else {
+        Style::default()
+    };
+    if app.dense {
+        Block::default().title(title).title_style(focus)
+    } else {
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_type(BorderType::Rounded)
+            .border_style(focus)
+    }
+}
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Shown while the connection is made, before there is an app to draw
+pub fn connecting(frame: &mut Frame, target: &str, tick: usize) {
+    let area = frame.size();
+    let text = format!(
+        "{} Connecting to {}...",
+        SPINNER[tick % SPINNER.len()],
+        target
+    );
+    let middle = Rect {
+        y: area.height / 2,
+        height: area.height.min(1),
+        ..area
+    };
+    frame.render_widget(Paragraph::new(text).alignment(Alignment::Center), middle);
+}
+
 pub fn ui<B: Backend>(frame: &mut Frame, app: &mut App) {
     // Create two chunks with equal horizontal screen space
     let full = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(5), Constraint::Max(2)])
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Max(if app.dense { 1 } else { 2 }),
+        ])
         .split(frame.size());
+    let widths = if frame.size().width >= NARROW_WIDTH {
+        [25, 75]
+    } else if app.working_area == WorkingArea::Services {
+        [100, 0]
+    } else {
+        [0, 100]
+    };
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(25), Constraint::Percentage(75)].as_ref())
+        .constraints(widths.map(Constraint::Percentage))
         .split(full[0]);
-    let items: Vec<ListItem> = app
-        .services
-        .items
-        .iter()
-        .map(|i| {
-            let lines = Span::from(i.as_str());
-            ListItem::new(lines).style(Style::default())
-        })
-        .collect();
+    // Room left for a name, after the borders and highlight symbol
+    let borders_width = if app.dense { 0 } else { 2 };
+    // Both panes are as high, with the title taking a line in dense mode
+    let borders_height = if app.dense { 1 } else { 2 };
+    app.page_size = usize::from(full[0].height)
+        .saturating_sub(borders_height)
+        .max(1);
+    let name_width = usize::from(chunks[0].width)
+        .saturating_sub(borders_width + app.theme.highlight_symbol.width());
+    let placeholder = if !app.services_listed {
+        "Loading services..."
+    } else if !app.service_filter.is_empty() || app.implements_filter().is_some() {
+        "No services match"
+    } else {
+        "No services found"
+    };
+    let items: Vec<ListItem> = match &app.peer {
+        Some(address) => vec![ListItem::new(Span::from(truncate_to_width(
+            address, name_width,
+        )))],
+        None if app.services.items.is_empty() => vec![ListItem::new(Span::styled(
+            placeholder,
+            Style::default().fg(Color::DarkGray),
+        ))],
+        None => app
+            .services
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, i)| {
+                let mut prefix = String::new();
+                if app.favorites.contains(i.as_str()) {
+                    prefix += "* ";
+                }
+                // Names owned by the connection above them are indented under it
+                let grouped = app.service_sort == ServiceSort::Owner
+                    && index > 0
+                    && !i.starts_with(':')
+                    && app.owner(i).is_some()
+                    && app.owner(i) == app.owner(&app.services.items[index - 1]);
+                if grouped {
+                    prefix += "└ ";
+                }
+                let name = format!(
+                    "{}{}",
+                    prefix,
+                    truncate_to_width(i.as_str(), name_width.saturating_sub(prefix.width()))
+                );
+                let lines = Span::from(name);
+                ListItem::new(lines).style(Style::default())
+            })
+            .collect(),
+    };
 
+    let mut services_title = match (&app.peer, app.implements_filter()) {
+        (Some(_), _) => "Peer".to_string(),
+        (None, Some(interface)) => format!(
+            "Services implementing {} ({})",
+            interface,
+            app.services.items.len()
+        ),
+        (None, None) => format!("Services ({})", app.services.items.len()),
+    };
+    if app.peer.is_none() {
+        services_title += match app.service_sort {
+            ServiceSort::Bus => "",
+            ServiceSort::Pid => " by PID",
+            ServiceSort::Owner => " by owner",
+        };
+    }
+    if app.filtering {
+        services_title += &format!(" /{}▏", app.service_filter);
+    } else if !app.service_filter.is_empty() {
+        services_title += &format!(" /{}", app.service_filter);
+    }
     // Create a List from all list items and highlight the currently selected one
     let items = List::new(items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Services")
-                .border_type(BorderType::Rounded)
-                .border_style(Style::default().fg(working_area_border(app, WorkingArea::Services))),
-        )
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-        .highlight_symbol(">> ");
+        .block(pane_block(app, WorkingArea::Services, services_title))
+        .highlight_style(app.theme.highlight_style)
+        .highlight_symbol(&app.theme.highlight_symbol);
 
     // We can now render the item list
     frame.render_stateful_widget(items, chunks[0], &mut app.services.state);
 
+    // Labels start after the highlight symbol and the symbol telling whether an item is open
+    app.fit_labels(
+        usize::from(chunks[1].width)
+            .saturating_sub(borders_width + app.theme.highlight_symbol.width() + 2),
+    );
+    let (object_count, interface_count) = app.objects.counts();
+    let objects_title = if app.walking {
+        format!("Objects (discovered {} objects...)", object_count)
+    } else if object_count == 0 {
+        "Objects".to_string()
+    } else {
+        format!(
+            "Objects ({} objects, {} interfaces)",
+            object_count, interface_count
+        )
+    };
+    let mut objects_block = pane_block(app, WorkingArea::Objects, objects_title);
+    // Flattened members are told apart by their prefix only
+    if app.tree_options.flatten_members {
+        objects_block = objects_block.title_bottom(
+            Line::styled(
+                "M method  P property  S signal",
+                Style::default().fg(Color::DarkGray),
+            )
+            .alignment(Alignment::Right),
+        );
+    }
+    if let Some(error) = &app.objects_error {
+        objects_block = objects_block.title_bottom(Line::styled(
+            error.as_str(),
+            Style::default().fg(Color::Red),
+        ));
+    }
     let objects_view = Tree::new(app.objects.items.clone())
         .unwrap()
+        .block(objects_block)
+        .highlight_style(app.theme.highlight_style)
+        .highlight_symbol(&app.theme.highlight_symbol);
+    frame.render_stateful_widget(objects_view, chunks[1], &mut app.objects.state);
+    if let Some(view) = &app.properties_view {
+        properties_popup(frame, view, app.popup_size, app.max_value_length);
+    }
+    if app.show_help {
+        help_popup(frame, app, full[0]);
+    }
+    let keys = |action| app.keymap.keys(action);
+    let navigation = [
+        Action::Collapse,
+        Action::Next,
+        Action::Previous,
+        Action::Expand,
+    ]
+    .map(keys);
+    // The rest of the keys are in the help popup
+    let help = if app.dense {
+        format!(
+            "{} focus | {} move | {} service | {} filter | ? keys | {} quit",
+            keys(Action::FocusNext),
+            navigation.concat(),
+            keys(Action::Select),
+            keys(Action::Search),
+            keys(Action::Quit)
+        )
+    } else {
+        format!(
+            "Change focus: {} | Navigation: {} | Get Service: {} | Filter: {} | All keys: ? | Quit: {}",
+            keys(Action::FocusNext),
+            navigation.join(" "),
+            keys(Action::Select),
+            keys(Action::Search),
+            keys(Action::Quit)
+        )
+    };
+    let bottom_text = match &app.status {
+        Some(status) => Span::styled(status.as_str(), Style::default().fg(Color::Yellow)),
+        None => Span::raw(help),
+    };
+    let helper_paragraph = Paragraph::new(bottom_text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    frame.render_widget(helper_paragraph, full[1]);
+    if !app.theme.use_color {
+        // Everything is drawn in the default colors, styles like bold are kept
+        for cell in frame.buffer_mut().content.iter_mut() {
+            cell.set_fg(Color::Reset).set_bg(Color::Reset);
+        }
+    }
+}
+
+fn properties_popup(
+    frame: &mut Frame,
+    view: &PropertiesView,
+    (width, height): (u16, u16),
+    max_value_length: usize,
+) {
+    let area = if view.full {
+        frame.size()
+    } else {
+        centered_rect(width, height, frame.size())
+    };
+    let lines: Vec<Line> = match &view.properties {
+        Ok(properties) if properties.is_empty() => vec![Line::from("No properties")],
+        Ok(properties) => properties
+            .iter()
+            .map(|(name, value)| {
+                Line::from(vec![
+                    Span::styled(name.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(": "),
+                    Span::raw(if view.full {
+                        Cow::Borrowed(value.as_str())
+                    } else {
+                        truncate_to_width(value, max_value_length)
+                    }),
+                ])
+            })
+            .collect(),
+        Err(err) => {
+            let mut lines = Vec::new();
+            if let Some(name) = &err.name {
+                lines.push(Line::styled(
+                    name.as_str(),
+                    Style::default()
+                        .fg(Color::Magenta)
+                        .add_modifier(Modifier::BOLD),
+                ));
+            }
+            lines.push(Line::styled(
+                err.message.as_str(),
+                Style::default().fg(Color::Red),
+            ));
+            if let Some(explanation) = err.explanation() {
+                lines.push(Line::styled(
+                    explanation,
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            lines
+        }
+    };
+    let popup = Paragraph::new(lines)
         .block(
             Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
-                .border_style(Style::default().fg(working_area_border(app, WorkingArea::Objects)))
-                .title("Objects"),
+                .border_style(Style::default().fg(Color::LightBlue))
+                .title(view.title.as_str())
+                .title_bottom("Scroll: ↓ ↑ | Full values: v | Resize: + - | Close: Esc"),
         )
-        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
-        .highlight_symbol(">> ");
-    frame.render_stateful_widget(objects_view, chunks[1], &mut app.objects.state);
-    let bottom_text =
-        Span::raw("Change focus: Tab | Navigation: ← ↓ ↑ → | Get Service: Enter | Quit: q");
-    let helper_paragraph = Paragraph::new(bottom_text).alignment(Alignment::Center);
-    frame.render_widget(helper_paragraph, full[1]);
+        .wrap(Wrap { trim: false })
+        .scroll((view.scroll, 0));
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+// Every key with what it does, keys bound in the keys file are shown as they are bound
+fn help_popup(frame: &mut Frame, app: &App, area: Rect) {
+    let keys = |action| app.keymap.keys(action);
+    let navigation = [
+        Action::Collapse,
+        Action::Next,
+        Action::Previous,
+        Action::Expand,
+    ]
+    .map(keys)
+    .join(" ");
+    let mut bindings = vec![
+        (keys(Action::FocusNext), "Change focus"),
+        (navigation, "Navigation"),
+        ("PgUp PgDn".to_string(), "Page, also Ctrl+u Ctrl+d"),
+        ("[ ]".to_string(), "Previous or next interface"),
+        (keys(Action::Select), "Get service"),
+        (keys(Action::Search), "Filter services"),
+        ("p".to_string(), "Sort services by PID"),
+        ("a".to_string(), "Group services by owner"),
+        ("*".to_string(), "Favorite service"),
+        ("g".to_string(), "Get all properties"),
+        ("G".to_string(), "Snapshot properties and getters"),
+        ("e".to_string(), "Export to markdown"),
+        ("c".to_string(), "Copy address"),
+        ("f".to_string(), "Flatten members"),
+        ("o".to_string(), "Sort members"),
+        ("b".to_string(), "Indent paths"),
+        ("d".to_string(), "Argument directions"),
+        ("t".to_string(), "Explain types"),
+        ("I".to_string(), "Implicit interfaces"),
+        ("s".to_string(), "Hide standard interfaces"),
+        ("D".to_string(), "Dense"),
+        (keys(Action::Quit), "Quit"),
+    ];
+    if app.implements.is_some() {
+        bindings.push(("i".to_string(), "Filter on --implements"));
+    }
+    let key_width = bindings
+        .iter()
+        .map(|(key, _)| key.width())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Line> = bindings
+        .iter()
+        .map(|(key, description)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{}{}  ", key, " ".repeat(key_width - key.width())),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*description),
+            ])
+        })
+        .collect();
+    let area = centered_rect(50, 90, area);
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::LightBlue))
+            .title("Keys")
+            .title_bottom("Close: Esc"),
+    );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+// A rectangle of the given percentages of area, centered in it
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
 }
@@ -1,11 +1,21 @@
 use std::io::BufReader;
 
 use async_recursion::async_recursion;
+use clap::Parser;
+use dtui::standard_interfaces::standard_interfaces;
 use zbus::fdo::DBusProxy;
 use zbus::names::OwnedBusName;
 use zbus::zvariant::ObjectPath;
 use zbus::{Connection, Result};
-use zbus_xml::Node;
+use zbus_xml::{Interface, Node};
+
+#[derive(Parser)]
+#[command(about = "Print every object and interface of the services on the session bus")]
+struct Args {
+    /// Also list the standard interfaces every object implements, when they aren't advertised
+    #[clap(long = "introspect-standard-interfaces")]
+    standard_interfaces: bool,
+}
 
 #[async_recursion]
 async fn print_all_interfaces(
@@ -13,6 +23,7 @@ async fn print_all_interfaces(
     service: &OwnedBusName,
     path: ObjectPath<'async_recursion>,
     indent: usize,
+    standard: bool,
 ) -> std::result::Result<(), zbus::Error> {
     println!("{:indent$}{} ", "", path.as_str(), indent = indent);
     let introspectable_proxy = zbus::fdo::IntrospectableProxy::builder(connection)
@@ -25,21 +36,23 @@ async fn print_all_interfaces(
     println!("{:indent$}Interfaces: ", "", indent = indent + 4);
     for interface in introspect.interfaces() {
         println!("{:indent$}{} ", "", interface.name(), indent = indent + 8);
-        println!("{:indent$}Methods: ", "", indent = indent + 12);
-        for method in interface.methods() {
-            println!("{:indent$}{} ", "", method.name(), indent = indent + 16);
-        }
-        println!("{:indent$}Signals: ", "", indent = indent + 12);
-        for signal in interface.signals() {
-            println!("{:indent$}{} ", "", signal.name(), indent = indent + 16);
-        }
-        println!("{:indent$}Properties: ", "", indent = indent + 12);
-        for property in interface.properties() {
-            println!("{:indent$}{} ", "", property.name(), indent = indent + 16);
-        }
-        println!("{:indent$}Annotations: ", "", indent = indent + 12);
-        for annotation in interface.annotations() {
-            println!("{:indent$}{} ", "", annotation.name(), indent = indent + 16);
+        print_members(interface, indent);
+    }
+    if standard {
+        for interface in standard_interfaces() {
+            if introspect
+                .interfaces()
+                .iter()
+                .all(|advertised| advertised.name() != interface.name())
+            {
+                println!(
+                    "{:indent$}{} (implicit) ",
+                    "",
+                    interface.name(),
+                    indent = indent + 8
+                );
+                print_members(interface, indent);
+            }
         }
     }
     for node in introspect.nodes() {
@@ -52,12 +65,33 @@ async fn print_all_interfaces(
         };
         let sub_path = ObjectPath::try_from(path_name)?;
 
-        print_all_interfaces(connection, service, sub_path, indent).await?;
+        print_all_interfaces(connection, service, sub_path, indent, standard).await?;
     }
     Ok(())
 }
+
+fn print_members(interface: &Interface, indent: usize) {
+    println!("{:indent$}Methods: ", "", indent = indent + 12);
+    for method in interface.methods() {
+        println!("{:indent$}{} ", "", method.name(), indent = indent + 16);
+    }
+    println!("{:indent$}Signals: ", "", indent = indent + 12);
+    for signal in interface.signals() {
+        println!("{:indent$}{} ", "", signal.name(), indent = indent + 16);
+    }
+    println!("{:indent$}Properties: ", "", indent = indent + 12);
+    for property in interface.properties() {
+        println!("{:indent$}{} ", "", property.name(), indent = indent + 16);
+    }
+    println!("{:indent$}Annotations: ", "", indent = indent + 12);
+    for annotation in interface.annotations() {
+        println!("{:indent$}{} ", "", annotation.name(), indent = indent + 16);
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
     let connection = Connection::session().await?;
 
     let dbusproxy = DBusProxy::new(&connection).await?;
@@ -70,7 +104,7 @@ async fn main() -> Result<()> {
         println!("Service: {}", service.as_str());
         let path_name = "/".to_string();
         let path = ObjectPath::try_from(path_name)?;
-        print_all_interfaces(&connection, &service, path, 4).await?;
+        print_all_interfaces(&connection, &service, path, 4, args.standard_interfaces).await?;
     }
 
     Ok(())
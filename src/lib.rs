@@ -0,0 +1,5 @@
+//! Building the tree of objects from introspection data. It is a library of its own so the benchmarks can reach it
+pub mod signature;
+pub mod standard_interfaces;
+pub mod stateful_tree;
+pub mod truncate;
@@ -0,0 +1,85 @@
+use zbus::zvariant::Signature;
+
+/// Describe a signature in words, like `dict of string → variant` for `a{sv}`
+pub fn describe_signature(signature: &Signature) -> String {
+    let mut chars = signature.as_str().chars().peekable();
+    let mut types = Vec::new();
+    while chars.peek().is_some() {
+        types.push(describe_type(&mut chars));
+    }
+    types.join(", ")
+}
+
+// Describe the single complete type at the start of chars, consuming it
+fn describe_type(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let Some(code) = chars.next() else {
+        return String::new();
+    };
+    match code {
+        'y' => "byte".to_string(),
+        'b' => "boolean".to_string(),
+        'n' => "int16".to_string(),
+        'q' => "uint16".to_string(),
+        'i' => "int32".to_string(),
+        'u' => "uint32".to_string(),
+        'x' => "int64".to_string(),
+        't' => "uint64".to_string(),
+        'd' => "double".to_string(),
+        'h' => "file descriptor".to_string(),
+        's' => "string".to_string(),
+        'o' => "object path".to_string(),
+        'g' => "signature".to_string(),
+        'v' => "variant".to_string(),
+        'a' if chars.peek() == Some(&'{') => {
+            chars.next();
+            let key = describe_type(chars);
+            let value = describe_type(chars);
+            chars.next_if_eq(&'}');
+            format!("dict of {} → {}", key, value)
+        }
+        'a' => format!("array of {}", describe_type(chars)),
+        '(' => {
+            let mut fields = Vec::new();
+            while chars.peek().is_some_and(|c| *c != ')') {
+                fields.push(describe_type(chars));
+            }
+            chars.next();
+            format!("struct of ({})", fields.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_nested_signatures() {
+        let cases = [
+            ("s", "string"),
+            ("a{sv}", "dict of string → variant"),
+            (
+                "a(oa{sa{sv}})",
+                "array of struct of (object path, dict of string → dict of string → variant)",
+            ),
+            ("(a(si))", "struct of (array of struct of (string, int32))"),
+            ("ay", "array of byte"),
+            (
+                "sa{sv}as",
+                "string, dict of string → variant, array of string",
+            ),
+        ];
+        for (signature, description) in cases {
+            let signature = Signature::try_from(signature).unwrap();
+            assert_eq!(describe_signature(&signature), description);
+        }
+    }
+
+    #[test]
+    fn unknown_type_codes_are_kept() {
+        // Not a valid signature, so it can only come from a service that got it wrong
+        let signature = Signature::from_str_unchecked("a{sz}");
+        assert_eq!(describe_signature(&signature), "dict of string → z");
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::OnceLock;
+
+use zbus_xml::{Interface, Node};
+
+// Every object implements these, but services often leave them out of their introspection data
+const STANDARD_INTERFACES_XML: &str = r#"
+<node>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+    <method name="GetMachineId">
+      <arg name="machine_uuid" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml_data" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg name="interface_name" type="s" direction="in"/>
+      <arg name="property_name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg name="interface_name" type="s" direction="in"/>
+      <arg name="props" type="a{sv}" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg name="interface_name" type="s" direction="in"/>
+      <arg name="property_name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg name="interface_name" type="s"/>
+      <arg name="changed_properties" type="a{sv}"/>
+      <arg name="invalidated_properties" type="as"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+/// The Peer, Introspectable and Properties interfaces
+pub fn standard_interfaces() -> &'static [Interface<'static>] {
+    static NODE: OnceLock<Node<'static>> = OnceLock::new();
+    NODE.get_or_init(|| {
+        Node::try_from(STANDARD_INTERFACES_XML).expect("Standard interfaces should be valid XML")
+    })
+    .interfaces()
+}
@@ -0,0 +1,757 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+use itertools::Itertools;
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use tui_tree_widget::{TreeItem, TreeState};
+use zbus::zvariant::Signature;
+use zbus_xml::{Arg, ArgDirection, Interface, Method, Node};
+
+use crate::{
+    signature::describe_signature, standard_interfaces::standard_interfaces,
+    truncate::truncate_to_width,
+};
+
+// Introspection data of each object path, or why it could not be introspected or parsed
+pub type Nodes = HashMap<String, Result<Node<'static>, String>>;
+
+/// Options controlling how the introspection data is laid out in the tree
+#[derive(Clone, Copy, Default)]
+pub struct TreeOptions {
+    /// Put methods, properties and signals directly under their interface instead of grouping them
+    pub flatten_members: bool,
+    /// Add the standard interfaces every object implements, even when they aren't advertised
+    pub standard_interfaces: bool,
+    /// Sort methods, properties and signals by name instead of keeping the introspection order
+    pub sort_members: bool,
+    /// Leave out the standard interfaces, to only show the API of the service itself
+    pub hide_standard_interfaces: bool,
+    /// Mark every argument with (in) or (out), instead of separating them with =>
+    pub explicit_directions: bool,
+    /// Describe types in words, like `dict of string → variant`, instead of showing signatures
+    pub explain_signatures: bool,
+    /// Indent object paths by their depth, like busctl tree
+    pub indent_paths: bool,
+    /// Columns left for labels at the top of the tree, longer labels are cut to fit
+    pub label_width: Option<usize>,
+}
+
+pub struct StatefulTree<'a> {
+    pub state: TreeState<usize>,
+    pub items: Vec<TreeItem<'a, usize>>,
+    // Object path and interface names of the items, indexed by their ids
+    names: Vec<(String, Vec<String>)>,
+    // Added to the root object when it is the only one, so a flat service doesn't show a lone /
+    root_label: Option<String>,
+}
+
+impl<'a> Default for StatefulTree<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> StatefulTree<'a> {
+    pub fn new() -> Self {
+        Self {
+            state: TreeState::default(),
+            items: Vec::new(),
+            names: Vec::new(),
+            root_label: None,
+        }
+    }
+
+    /// A tree whose root object is labeled with the service or peer it belongs to
+    pub fn with_root_label(label: String) -> Self {
+        Self {
+            root_label: Some(label),
+            ..Self::new()
+        }
+    }
+
+    /// Rebuild the items from nodes, keeping the current selection and opened items.
+    /// Objects keep their ids, so the tree can grow while objects are still being found
+    pub fn rebuild(&mut self, nodes: &Nodes, options: TreeOptions) {
+        (self.items, self.names) =
+            node_to_treeitems(nodes, options, &self.names, self.root_label.as_deref());
+    }
+
+    /// Add the objects at paths, which were just put in nodes, building items only for them.
+    /// Batches come in every few hundred milliseconds while a big service is walked, rebuilding
+    /// every object for each of them would get slower the more objects were found
+    pub fn add_nodes<'p>(
+        &mut self,
+        nodes: &Nodes,
+        paths: impl IntoIterator<Item = &'p String>,
+        options: TreeOptions,
+    ) {
+        let paths: Vec<&String> = paths.into_iter().sorted().dedup().collect();
+        let known: HashSet<&str> = self.names.iter().map(|(path, _)| path.as_str()).collect();
+        // A lone root object loses its label once others join it, and objects found again may have changed
+        if self.names.len() < 2 || paths.iter().any(|path| known.contains(path.as_str())) {
+            return self.rebuild(nodes, options);
+        }
+        // The items are in path order, so the new ones are merged in where they belong
+        let old_items = std::mem::take(&mut self.items);
+        let shown: Vec<&str> = self.object_ranks().map(|id| &*self.names[id].0).collect();
+        let mut old = shown.into_iter().zip(old_items).peekable();
+        let mut items = Vec::with_capacity(self.names.len() + paths.len());
+        let mut names = Vec::with_capacity(paths.len());
+        for path in paths {
+            let id = self.names.len() + names.len();
+            let (item, interface_names) = object_treeitem(id, path, &nodes[path], options, None);
+            items.extend(
+                old.peeking_take_while(|(shown, _)| *shown < path.as_str())
+                    .map(|(_, item)| item),
+            );
+            items.push(item);
+            names.push((path.clone(), interface_names));
+        }
+        items.extend(old.map(|(_, item)| item));
+        self.items = items;
+        self.names.extend(names);
+    }
+
+    /// Object path and interface name of the selected interface, or of the interface the selection is in
+    pub fn selected_interface(&self) -> Option<(&str, &str)> {
+        match self.state.selected()[..] {
+            [object, interface, ..] => {
+                let (path, interfaces) = self.names.get(object)?;
+                Some((path, interfaces.get(interface)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Number of objects and interfaces in the tree
+    pub fn counts(&self) -> (usize, usize) {
+        let interfaces = self
+            .names
+            .iter()
+            .map(|(_, interfaces)| interfaces.len())
+            .sum();
+        (self.names.len(), interfaces)
+    }
+
+    /// Select the interface after the current selection, opening its object if needed
+    pub fn next_interface(&mut self) {
+        let current = self.selected_position();
+        let next = self
+            .interface_positions()
+            .find(|(position, _)| Some(*position) > current);
+        if let Some((_, next)) = next {
+            self.select_interface(next);
+        }
+    }
+
+    /// Select the interface before the current selection, opening its object if needed
+    pub fn previous_interface(&mut self) {
+        let Some(current) = self.selected_position() else {
+            return;
+        };
+        let previous = self
+            .interface_positions()
+            .filter(|(position, _)| *position < current)
+            .last();
+        if let Some((_, previous)) = previous {
+            self.select_interface(previous);
+        }
+    }
+
+    // Where the selection is shown, as the rank of its object in path order and its interface.
+    // Members are at their interface, and an object comes before its interfaces
+    fn selected_position(&self) -> Option<(usize, Option<usize>)> {
+        let selected = self.state.selected();
+        let object = *selected.first()?;
+        let rank = self.object_ranks().position(|id| id == object)?;
+        Some((rank, selected.get(1).copied()))
+    }
+
+    // Object ids in the order they are shown, which is by path and not the order they were found in
+    fn object_ranks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.names
+            .iter()
+            .enumerate()
+            .sorted_by(|a, b| a.1 .0.cmp(&b.1 .0))
+            .map(|(object, _)| object)
+    }
+
+    // Ids of all interfaces in the order they are shown, each with its position
+    fn interface_positions(
+        &self,
+    ) -> impl Iterator<Item = ((usize, Option<usize>), Vec<usize>)> + '_ {
+        self.object_ranks().enumerate().flat_map(|(rank, object)| {
+            (0..self.names[object].1.len())
+                .map(move |interface| ((rank, Some(interface)), vec![object, interface]))
+        })
+    }
+
+    /// Select an interface of an object and open it up to its methods, returns whether it is in the tree
+    pub fn open_interface(&mut self, path: &str, interface: &str) -> bool {
+        let ids = self
+            .names
+            .iter()
+            .enumerate()
+            .find_map(|(object, (name, interfaces))| {
+                let position = interfaces.iter().position(|name| name == interface);
+                position
+                    .filter(|_| name == path)
+                    .map(|position| vec![object, position])
+            });
+        match ids {
+            Some(ids) => {
+                // The methods are the first group, when members are flattened this is a leaf and nothing opens
+                self.state.open([ids.clone(), vec![0]].concat());
+                self.state.open(ids.clone());
+                self.select_interface(ids);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn select_interface(&mut self, ids: Vec<usize>) {
+        self.state.open(ids[..1].to_vec());
+        self.state.select(ids);
+    }
+
+    pub fn down(&mut self) {
+        self.state.key_down(&self.items);
+    }
+
+    pub fn up(&mut self) {
+        self.state.key_up(&self.items);
+    }
+
+    /// Move the selection down by step visible items, stopping at the last
+    pub fn down_by(&mut self, step: usize) {
+        self.state.select_visible_relative(&self.items, |current| {
+            current.map_or(0, |current| current.saturating_add(step))
+        });
+    }
+
+    /// Move the selection up by step visible items, stopping at the first
+    pub fn up_by(&mut self, step: usize) {
+        self.state.select_visible_relative(&self.items, |current| {
+            current.map_or(0, |current| current.saturating_sub(step))
+        });
+    }
+
+    pub fn left(&mut self) {
+        self.state.key_left();
+    }
+
+    pub fn right(&mut self) {
+        self.state.key_right();
+    }
+
+    pub fn toggle(&mut self) {
+        self.state.toggle_selected();
+    }
+}
+
+type Names = Vec<(String, Vec<String>)>;
+
+// Objects are shown sorted by path, but their ids are given in the order they were found.
+// Objects that were in previous keep their id. A lone root object is labeled with root_label
+fn node_to_treeitems(
+    nodes: &Nodes,
+    options: TreeOptions,
+    previous: &Names,
+    root_label: Option<&str>,
+) -> (Vec<TreeItem<'static, usize>>, Names) {
+    let known: HashSet<&str> = previous.iter().map(|(path, _)| path.as_str()).collect();
+    // The id of an object is its position in paths
+    let paths: Vec<&String> = previous
+        .iter()
+        .filter_map(|(path, _)| nodes.get_key_value(path).map(|(path, _)| path))
+        .chain(
+            nodes
+                .keys()
+                .filter(|path| !known.contains(path.as_str()))
+                .sorted(),
+        )
+        .collect();
+    let root_label = root_label.filter(|_| nodes.len() == 1);
+    // Built in id order so names can be pushed as they come, the items are sorted by path afterwards
+    let mut items = Vec::with_capacity(paths.len());
+    let mut names = Vec::with_capacity(paths.len());
+    for (id, path) in paths.into_iter().enumerate() {
+        let (item, interface_names) = object_treeitem(id, path, &nodes[path], options, root_label);
+        items.push((path, item));
+        names.push((path.clone(), interface_names));
+    }
+    items.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    let items = items.into_iter().map(|(_, item)| item).collect();
+    (items, names)
+}
+
+// The item of one object and the names of its interfaces. The root object is labeled with root_label if given
+fn object_treeitem(
+    id: usize,
+    path: &str,
+    node: &Result<Node<'static>, String>,
+    options: TreeOptions,
+    root_label: Option<&str>,
+) -> (TreeItem<'static, usize>, Vec<String>) {
+    let indent = if options.indent_paths {
+        "  ".repeat(path.matches('/').count() - usize::from(path == "/"))
+    } else {
+        String::new()
+    };
+    match node {
+        Ok(node) => {
+            let interfaces = object_interfaces(node, options);
+            let interface_names = interfaces
+                .iter()
+                .map(|(interface, _)| interface.name().to_string())
+                .collect();
+            let children: Vec<_> = interfaces
+                .into_iter()
+                .enumerate()
+                .map(|(id, (interface, implicit))| {
+                    interface_to_treeitem(id, interface, implicit, options)
+                })
+                .collect();
+            let (label, mut note) = match root_label {
+                Some(root_label) if path == "/" => ("/ ".to_string(), root_label.to_string()),
+                _ => (format!("{}{}", indent, path), String::new()),
+            };
+            // Tells an object that exposes nothing apart from one whose interfaces are hidden.
+            // Objects that only hold others, like /org, are not marked
+            if children.is_empty() && node.interfaces().is_empty() && node.nodes().is_empty() {
+                note.push_str(" (no interfaces)");
+            }
+            let text = object_label(label, note, options);
+            (TreeItem::new(id, text, children).unwrap(), interface_names)
+        }
+        Err(err) => (
+            error_treeitem(id, &format!("{}{}", indent, path), err, options),
+            Vec::new(),
+        ),
+    }
+}
+
+// The label of an object followed by a gray note, cut together to the width at the top of the tree
+fn object_label(label: String, note: String, options: TreeOptions) -> Line<'static> {
+    if note.is_empty() {
+        return Line::from(fit(label, 0, options));
+    }
+    let whole = fit(format!("{}{}", label, note), 0, options);
+    match whole.strip_prefix(label.as_str()) {
+        Some(note) => {
+            let note = Span::styled(note.to_string(), Style::default().fg(Color::DarkGray));
+            Line::from(vec![Span::raw(label), note])
+        }
+        // Cut within the label, so nothing of the note is left
+        None => Line::from(whole),
+    }
+}
+
+// The interfaces of an object, followed by the standard interfaces it leaves out if they are shown.
+// The standard interfaces are marked as implicit
+fn object_interfaces<'n>(node: &'n Node, options: TreeOptions) -> Vec<(&'n Interface<'n>, bool)> {
+    let implicit_interfaces = standard_interfaces().iter().filter(|standard| {
+        options.standard_interfaces
+            && !node
+                .interfaces()
+                .iter()
+                .any(|interface| interface.name() == standard.name())
+    });
+    node.interfaces()
+        .iter()
+        .map(|interface| (interface, false))
+        .chain(implicit_interfaces.map(|interface| (interface, true)))
+        .filter(|(interface, _)| {
+            !(options.hide_standard_interfaces && is_standard_interface(interface.name().as_str()))
+        })
+        .collect()
+}
+
+// Whether the interface is one of the well-known interfaces of the D-Bus specification,
+// rather than part of the API of a service
+fn is_standard_interface(name: &str) -> bool {
+    matches!(
+        name,
+        "org.freedesktop.DBus.Peer"
+            | "org.freedesktop.DBus.Introspectable"
+            | "org.freedesktop.DBus.Properties"
+            | "org.freedesktop.DBus.ObjectManager"
+    )
+}
+
+// An object that could not be introspected or whose data could not be parsed, with the error as its only child
+fn error_treeitem(
+    id: usize,
+    object_name: &str,
+    err: &str,
+    options: TreeOptions,
+) -> TreeItem<'static, usize> {
+    let error = fit(err.to_string(), 1, options);
+    let error = TreeItem::new_leaf(0, error).style(Style::default().fg(Color::Red));
+    TreeItem::new(id, fit(object_name.to_string(), 0, options), vec![error]).unwrap()
+}
+
+// Cuts a label to the width left for it at its depth in the tree, each level is indented by two columns.
+// Labels that fit are given back as they are, without copying them
+fn fit(label: String, depth: usize, options: TreeOptions) -> String {
+    let Some(width) = options.label_width else {
+        return label;
+    };
+    match truncate_to_width(&label, width.saturating_sub(depth * 2)) {
+        Cow::Borrowed(_) => label,
+        Cow::Owned(truncated) => truncated,
+    }
+}
+
+fn interface_to_treeitem(
+    id: usize,
+    interface: &Interface,
+    implicit: bool,
+    options: TreeOptions,
+) -> TreeItem<'static, usize> {
+    let explain = options.explain_signatures;
+    let methods = interface
+        .methods()
+        .iter()
+        .map(|method| (method.name(), member_method_label(method, options)));
+    let properties = interface.properties().iter().map(|property| {
+        (
+            property.name(),
+            format!(
+                "{}: {}",
+                property.name(),
+                type_label(property.ty().signature(), explain)
+            ),
+        )
+    });
+    let signals = interface.signals().iter().map(|signal| {
+        (
+            signal.name(),
+            signal_label(signal.name().as_str(), signal.args(), explain),
+        )
+    });
+    let methods = ordered(methods, options.sort_members);
+    let properties = ordered(properties, options.sort_members);
+    let signals = ordered(signals, options.sort_members);
+    // TODO: Annotations are used differently, so i dont want to waste space with it
+    let children = if options.flatten_members {
+        methods
+            .map(|label| format!("M {}", label))
+            .chain(properties.map(|label| format!("P {}", label)))
+            .chain(signals.map(|label| format!("S {}", label)))
+            .enumerate()
+            .map(|(id, label)| TreeItem::new_leaf(id, Line::from(fit(label, 2, options))))
+            .collect()
+    } else {
+        vec![
+            TreeItem::new(0, "Methods", leaves(methods, options))
+                .expect("Methods should have different ids"),
+            TreeItem::new(1, "Properties", leaves(properties, options))
+                .expect("Properties should have different ids"),
+            TreeItem::new(2, "Signals", leaves(signals, options))
+                .expect("Signals should have different ids"),
+        ]
+    };
+    if implicit {
+        let label = fit(format!("{} (implicit)", interface.name()), 1, options);
+        TreeItem::new(id, Line::from(label), children)
+            .unwrap()
+            .style(Style::default().fg(Color::DarkGray))
+    } else {
+        let label = fit(interface.name().to_string(), 1, options);
+        TreeItem::new(id, Line::from(label), children).unwrap()
+    }
+}
+
+// The label of a method in the tree, which is the method_label of its arguments unless every argument
+// is marked with its direction. Written in place, without collecting the arguments first
+fn member_method_label(method: &Method, options: TreeOptions) -> String {
+    let explain = options.explain_signatures;
+    let mut label = format!("{}(", method.name());
+    let has = |direction| move |arg: &Arg| arg.direction() == Some(direction);
+    if options.explicit_directions {
+        write_args(&mut label, method.args(), |_| true, true, explain);
+        label.push(')');
+    } else {
+        write_args(
+            &mut label,
+            method.args(),
+            has(ArgDirection::In),
+            false,
+            explain,
+        );
+        label.push(')');
+        if method.args().iter().any(has(ArgDirection::Out)) {
+            label.push_str(" => ");
+            write_args(
+                &mut label,
+                method.args(),
+                has(ArgDirection::Out),
+                false,
+                explain,
+            );
+        }
+    }
+    label
+}
+
+// Labels of (name, label) pairs, sorted by name if asked, otherwise in introspection order
+fn ordered<N: Ord>(
+    members: impl Iterator<Item = (N, String)>,
+    sort: bool,
+) -> impl Iterator<Item = String> {
+    let mut members: Vec<(N, String)> = members.collect();
+    if sort {
+        members.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    members.into_iter().map(|(_, label)| label)
+}
+
+// Members under their group, three levels down
+fn leaves(
+    labels: impl Iterator<Item = String>,
+    options: TreeOptions,
+) -> Vec<TreeItem<'static, usize>> {
+    labels
+        .enumerate()
+        .map(|(id, label)| TreeItem::new_leaf(id, Line::from(fit(label, 3, options))))
+        .collect()
+}
+
+pub fn args_with_direction(args: &[Arg], direction: ArgDirection, explain: bool) -> Vec<String> {
+    args.iter()
+        .filter(|arg| arg.direction().is_some_and(|s| s == direction))
+        .map(|arg| {
+            format!(
+                "{}: {}",
+                arg.name().unwrap_or_default(),
+                type_label(arg.ty().signature(), explain)
+            )
+        })
+        .collect()
+}
+
+// Writes the arguments that are kept in their order, separated by commas, each marked with its direction if asked.
+// Labels are written in place, as the arguments of every method would otherwise be formatted one by one
+fn write_args(
+    label: &mut String,
+    args: &[Arg],
+    keep: impl Fn(&Arg) -> bool,
+    marked: bool,
+    explain: bool,
+) {
+    for (position, arg) in args.iter().filter(|arg| keep(arg)).enumerate() {
+        if position > 0 {
+            label.push_str(", ");
+        }
+        label.push_str(arg.name().unwrap_or_default());
+        label.push_str(": ");
+        label.push_str(&type_label(arg.ty().signature(), explain));
+        if marked {
+            label.push_str(match arg.direction() {
+                Some(ArgDirection::In) => " (in)",
+                Some(ArgDirection::Out) => " (out)",
+                None => "",
+            });
+        }
+    }
+}
+
+// The signature itself, or its description when explaining signatures
+fn type_label<'s>(signature: &'s Signature, explain: bool) -> Cow<'s, str> {
+    if explain {
+        Cow::Owned(describe_signature(signature))
+    } else {
+        Cow::Borrowed(signature.as_str())
+    }
+}
+
+/// Formats a signal as `name(args)`. Signal arguments are usually given without a direction,
+/// as they can only be sent, so all of them are shown
+pub fn signal_label(name: &str, args: &[Arg], explain: bool) -> String {
+    let mut label = format!("{}(", name);
+    write_args(&mut label, args, |_| true, false, explain);
+    label.push(')');
+    label
+}
+
+/// Formats a method as `name(inputs)`, followed by ` => outputs` only if the method returns anything
+pub fn method_label(name: &str, inputs: &[String], outputs: &[String]) -> String {
+    if outputs.is_empty() {
+        format!("{}({})", name, inputs.join(", "))
+    } else {
+        format!("{}({}) => {}", name, inputs.join(", "), outputs.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn method_label_without_arguments() {
+        assert_eq!(method_label("Ping", &[], &[]), "Ping()");
+    }
+
+    #[test]
+    fn method_label_with_inputs_only() {
+        let inputs = labels(&["name: s", "flags: u"]);
+        assert_eq!(
+            method_label("Request", &inputs, &[]),
+            "Request(name: s, flags: u)"
+        );
+    }
+
+    #[test]
+    fn method_label_with_outputs_only() {
+        let outputs = labels(&["machine_uuid: s"]);
+        assert_eq!(
+            method_label("GetMachineId", &[], &outputs),
+            "GetMachineId() => machine_uuid: s"
+        );
+    }
+
+    #[test]
+    fn method_label_with_inputs_and_outputs() {
+        let inputs = labels(&["interface_name: s", "property_name: s"]);
+        let outputs = labels(&["value: v", "found: b"]);
+        assert_eq!(
+            method_label("Get", &inputs, &outputs),
+            "Get(interface_name: s, property_name: s) => value: v, found: b"
+        );
+    }
+
+    #[test]
+    fn methods_in_the_tree_are_labeled_like_method_label() {
+        let xml = r#"<node><interface name="org.example.Calc"><method name="Add">
+            <arg name="a" type="i" direction="in"/><arg name="b" type="i" direction="in"/>
+            <arg name="sum" type="x" direction="out"/></method></interface></node>"#;
+        let node = Node::from_reader(xml.as_bytes()).unwrap();
+        let method = &node.interfaces()[0].methods()[0];
+        let inputs = args_with_direction(method.args(), ArgDirection::In, false);
+        let outputs = args_with_direction(method.args(), ArgDirection::Out, false);
+        assert_eq!(
+            member_method_label(method, TreeOptions::default()),
+            method_label("Add", &inputs, &outputs)
+        );
+        let explicit = TreeOptions {
+            explicit_directions: true,
+            ..TreeOptions::default()
+        };
+        assert_eq!(
+            member_method_label(method, explicit),
+            "Add(a: i (in), b: i (in), sum: x (out))"
+        );
+    }
+
+    // Members as an interface could list them, not sorted by name
+    fn out_of_order_members() -> impl Iterator<Item = (String, String)> {
+        ["Set", "Get", "GetAll"]
+            .into_iter()
+            .map(|name| (name.to_string(), format!("{}()", name)))
+    }
+
+    #[test]
+    fn ordered_keeps_introspection_order() {
+        let labels: Vec<String> = ordered(out_of_order_members(), false).collect();
+        assert_eq!(labels, ["Set()", "Get()", "GetAll()"]);
+    }
+
+    #[test]
+    fn ordered_sorts_by_name() {
+        let labels: Vec<String> = ordered(out_of_order_members(), true).collect();
+        assert_eq!(labels, ["Get()", "GetAll()", "Set()"]);
+    }
+
+    fn node(interface: &str) -> Result<Node<'static>, String> {
+        let xml = format!(r#"<node><interface name="{}"/></node>"#, interface);
+        Ok(Node::from_reader(xml.as_bytes()).unwrap())
+    }
+
+    // /b is found before /a, so its id comes first even though it is shown last
+    fn tree_found_out_of_order() -> StatefulTree<'static> {
+        let mut tree = StatefulTree::new();
+        let mut nodes = Nodes::new();
+        nodes.insert("/b".to_string(), node("org.example.B"));
+        tree.rebuild(&nodes, TreeOptions::default());
+        nodes.insert("/a".to_string(), node("org.example.A"));
+        tree.rebuild(&nodes, TreeOptions::default());
+        tree
+    }
+
+    #[test]
+    fn added_nodes_are_merged_in_path_order() {
+        let mut nodes = Nodes::new();
+        for path in ["/c", "/a", "/e"] {
+            nodes.insert(path.to_string(), node("org.example.Old"));
+        }
+        let mut added = StatefulTree::new();
+        added.rebuild(&nodes, TreeOptions::default());
+        let paths = ["/d".to_string(), "/b".to_string(), "/f".to_string()];
+        for path in &paths {
+            nodes.insert(path.clone(), node("org.example.New"));
+        }
+        added.add_nodes(&nodes, &paths, TreeOptions::default());
+        let mut rebuilt = StatefulTree::new();
+        rebuilt.names = added.names.clone();
+        rebuilt.rebuild(&nodes, TreeOptions::default());
+        assert_eq!(format!("{:?}", added.items), format!("{:?}", rebuilt.items));
+        assert_eq!(added.names, rebuilt.names);
+    }
+
+    #[test]
+    fn object_labels_fit_with_their_note() {
+        let options = TreeOptions {
+            label_width: Some(12),
+            ..TreeOptions::default()
+        };
+        let label = object_label("/ ".to_string(), "org.example.Service".to_string(), options);
+        assert_eq!(label.to_string(), "/ org.examp…");
+        assert_eq!(label.spans[1].style.fg, Some(Color::DarkGray));
+        let label = object_label("/a".to_string(), " (no interfaces)".to_string(), options);
+        assert_eq!(label.to_string(), "/a (no inte…");
+        let label = object_label(
+            "/org/example".to_string(),
+            " (no interfaces)".to_string(),
+            options,
+        );
+        assert_eq!(label.to_string(), "/org/exampl…");
+        let label = object_label(
+            "/a".to_string(),
+            " (no interfaces)".to_string(),
+            TreeOptions::default(),
+        );
+        assert_eq!(label.to_string(), "/a (no interfaces)");
+    }
+
+    #[test]
+    fn next_interface_follows_the_shown_order() {
+        let mut tree = tree_found_out_of_order();
+        tree.next_interface();
+        assert_eq!(tree.selected_interface(), Some(("/a", "org.example.A")));
+        tree.next_interface();
+        assert_eq!(tree.selected_interface(), Some(("/b", "org.example.B")));
+        tree.next_interface();
+        assert_eq!(tree.selected_interface(), Some(("/b", "org.example.B")));
+    }
+
+    #[test]
+    fn previous_interface_follows_the_shown_order() {
+        let mut tree = tree_found_out_of_order();
+        assert!(tree.open_interface("/b", "org.example.B"));
+        tree.previous_interface();
+        assert_eq!(tree.selected_interface(), Some(("/a", "org.example.A")));
+        tree.previous_interface();
+        assert_eq!(tree.selected_interface(), Some(("/a", "org.example.A")));
+    }
+}
@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Cuts text to fit in width columns of the terminal, ending it with an ellipsis if anything was cut
+pub fn truncate_to_width(text: &str, width: usize) -> Cow<'_, str> {
+    if text.width() <= width {
+        return Cow::Borrowed(text);
+    }
+    if width == 0 {
+        return Cow::Borrowed("");
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        // Leave room for the ellipsis
+        if used + char_width > width - 1 {
+            break;
+        }
+        used += char_width;
+        truncated.push(c);
+    }
+    truncated.push('…');
+    Cow::Owned(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_cut_to_the_width_with_an_ellipsis() {
+        let cases = [
+            // Exact fit, nothing is cut
+            ("abc", 3, "abc"),
+            ("abc", 2, "a…"),
+            // Only the ellipsis fits
+            ("abc", 1, "…"),
+            ("abc", 0, ""),
+            ("", 0, ""),
+            // A wide character that would straddle the cut is left out whole
+            ("a中b", 3, "a…"),
+            ("中文字", 5, "中文…"),
+            ("🦀🦀", 3, "🦀…"),
+        ];
+        for (text, width, truncated) in cases {
+            assert_eq!(truncate_to_width(text, width), truncated);
+            assert!(truncate_to_width(text, width).width() <= width);
+        }
+    }
+
+    #[test]
+    fn text_that_fits_is_borrowed() {
+        assert!(matches!(
+            truncate_to_width("中文", 4),
+            Cow::Borrowed("中文")
+        ));
+    }
+}